@@ -2,9 +2,13 @@
 //!
 //! Contains all the types required to construct an AST for any mathematical expression.
 
+use std::f64::consts::PI;
 use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Clone)]
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 /// Defines all the different kinds of mathematical expressions
 /// as recursive types.
 pub enum Expr {
@@ -14,27 +18,49 @@ pub enum Expr {
     Mul(Box<Expr>, Box<Expr>),
     Div(Box<Expr>, Box<Expr>),
     Pow(Box<Expr>, Box<Expr>),
+    Mod(Box<Expr>, Box<Expr>),
     Neg(Box<Expr>),
-    Abs(Box<Expr>),
-    Floor(Box<Expr>),
-    Log(Box<Expr>),
-    Ln(Box<Expr>),
-    Sin(Box<Expr>),
-    Cos(Box<Expr>),
-    Tan(Box<Expr>),
-    Arcsin(Box<Expr>),
-    Arccos(Box<Expr>),
-    Arctan(Box<Expr>),
+    Fact(Box<Expr>),
+    /// An angle given in a particular unit, as in `90 deg`.
+    Angle(Box<Expr>, AngleMode),
+    /// A quantity converted to other units, as in `60 mph to m/s`.
+    Convert(Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
     Ident(String),
+    /// A result from the log of calculations: the `n`th for a positive
+    /// `n`, as in `$3`, or `-n` results back for a negative one, as in `$-2`,
+    /// along with how it was written.
+    History(i64, String),
     // First can only actually be Ident
     Assign(String, Box<Expr>),
+    /// A call to a user defined function.
+    Apply(String, Vec<Expr>),
+    /// A user defined function: its name, parameters and body.
+    Function(String, Vec<String>, Box<Expr>),
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
+/// A byte range into the source input, used to point at
+/// where a token (or an error) came from.
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
 /// Defines all the supported functions.
 pub enum Func {
     Abs,
     Floor,
+    Ceil,
+    Round,
+    Sqrt,
     Log,
     Ln,
     Sin,
@@ -43,14 +69,165 @@ pub enum Func {
     Arcsin,
     Arccos,
     Arctan,
+    Atan2,
+    Hypot,
+    Max,
+    Min,
+}
+
+/// Every built in function.
+pub const FUNCTIONS: &[Func] = &[
+    Func::Abs,
+    Func::Floor,
+    Func::Ceil,
+    Func::Round,
+    Func::Sqrt,
+    Func::Log,
+    Func::Ln,
+    Func::Sin,
+    Func::Cos,
+    Func::Tan,
+    Func::Arcsin,
+    Func::Arccos,
+    Func::Arctan,
+    Func::Atan2,
+    Func::Hypot,
+    Func::Max,
+    Func::Min,
+];
+
+impl Func {
+    /// Returns the name this function is called by.
+    pub fn name(&self) -> &'static str {
+        use self::Func::*;
+
+        match *self {
+            Abs => "abs",
+            Floor => "floor",
+            Ceil => "ceil",
+            Round => "round",
+            Sqrt => "sqrt",
+            Log => "log",
+            Ln => "ln",
+            Sin => "sin",
+            Cos => "cos",
+            Tan => "tan",
+            Arcsin => "arcsin",
+            Arccos => "arccos",
+            Arctan => "arctan",
+            Atan2 => "atan2",
+            Hypot => "hypot",
+            Max => "max",
+            Min => "min",
+        }
+    }
+
+    /// Returns the minimum and maximum number of arguments this function
+    /// accepts. A maximum of `None` means any number of arguments.
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        use self::Func::*;
+
+        match *self {
+            // log(x) is base 10, log(x, b) is base b.
+            // round(x) rounds to an integer, round(x, d) to d decimal places.
+            Log | Round => (1, Some(2)),
+            Atan2 | Hypot => (2, Some(2)),
+            Max | Min => (1, None),
+            _ => (1, Some(1)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize, Default)]
+/// Defines the units angles can be measured in.
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+    Gradians,
+}
+
+impl AngleMode {
+    /// Converts an angle in units of this mode to radians.
+    pub fn to_radians(self, x: f64) -> f64 {
+        match self {
+            AngleMode::Radians => x,
+            AngleMode::Degrees => x.to_radians(),
+            AngleMode::Gradians => x * (PI / 200.0),
+        }
+    }
+
+    /// Converts an angle in radians to units of this mode.
+    pub fn from_radians(self, x: f64) -> f64 {
+        match self {
+            AngleMode::Radians => x,
+            AngleMode::Degrees => x.to_degrees(),
+            AngleMode::Gradians => x * (200.0 / PI),
+        }
+    }
+
+    /// Returns the size of a full turn in units of this mode.
+    pub fn turn(self) -> f64 {
+        match self {
+            AngleMode::Radians => 2.0 * PI,
+            AngleMode::Degrees => 360.0,
+            AngleMode::Gradians => 400.0,
+        }
+    }
+}
+
+impl fmt::Display for AngleMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AngleMode::Radians => write!(f, "radians"),
+            AngleMode::Degrees => write!(f, "degrees"),
+            AngleMode::Gradians => write!(f, "gradians"),
+        }
+    }
+}
+
+impl FromStr for AngleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rad" | "radian" | "radians" => Ok(AngleMode::Radians),
+            "deg" | "degree" | "degrees" => Ok(AngleMode::Degrees),
+            "grad" | "gradian" | "gradians" => Ok(AngleMode::Gradians),
+            _ => Err(format!("Unknown angle mode: {}", s)),
+        }
+    }
+}
+
+/// Takes a &str and returns a corresponding angle unit token,
+/// if there is one. Otherwise it returns None.
+pub fn get_angle_token(s: &str) -> Option<Token> {
+    match s {
+        "rad" => Some(Token::Angle(AngleMode::Radians)),
+        "deg" => Some(Token::Angle(AngleMode::Degrees)),
+        "grad" => Some(Token::Angle(AngleMode::Gradians)),
+        _ => None,
+    }
+}
+
+/// Takes a &str and returns a corresponding keyword token,
+/// if there is one. Otherwise it returns None.
+pub fn get_keyword_token(s: &str) -> Option<Token> {
+    match s {
+        "to" | "in" => Some(Token::Convert),
+        _ => None,
+    }
 }
 
 /// Takes a &str and returns a corresponding function token,
 /// if there is one. Otherwise it returns None.
-pub fn get_function_token<'a>(s: &'a str) -> Option<Token> {
+pub fn get_function_token(s: &str) -> Option<Token> {
     match s {
         "abs" => Some(Token::Func(Func::Abs)),
         "floor" => Some(Token::Func(Func::Floor)),
+        "ceil" => Some(Token::Func(Func::Ceil)),
+        "round" => Some(Token::Func(Func::Round)),
+        "sqrt" => Some(Token::Func(Func::Sqrt)),
         "log" => Some(Token::Func(Func::Log)),
         "ln" => Some(Token::Func(Func::Ln)),
         "sin" => Some(Token::Func(Func::Sin)),
@@ -59,6 +236,10 @@ pub fn get_function_token<'a>(s: &'a str) -> Option<Token> {
         "arcsin" => Some(Token::Func(Func::Arcsin)),
         "arccos" => Some(Token::Func(Func::Arccos)),
         "arctan" => Some(Token::Func(Func::Arctan)),
+        "atan2" => Some(Token::Func(Func::Atan2)),
+        "hypot" => Some(Token::Func(Func::Hypot)),
+        "max" => Some(Token::Func(Func::Max)),
+        "min" => Some(Token::Func(Func::Min)),
         _ => None,
     }
 }
@@ -72,28 +253,39 @@ pub enum Token {
     Mul,
     Div,
     Pow,
+    Mod,
+    Fact,
     RParen,
     LParen,
+    Comma,
     Equals,
     Func(Func),
+    Angle(AngleMode),
+    /// The `to` or `in` of a unit conversion.
+    Convert,
     Ident(String),
+    /// A reference to an earlier result, as in `$3`, `_3` or `$-2`.
+    History(i64),
+    /// A power written in superscript, as in `x²` or `x⁻¹`.
+    Superscript(f64),
     Eof,
 }
 
 impl Token {
-    /// Returns the precedence of this Token.
-    pub fn get_precedence(&self) -> Precedence {
-        use self::Precedence::*;
-        use self::Token::*;
+    /// Returns the prefix operator this Token stands for, if any.
+    pub fn prefix_op(&self) -> Option<&'static Operator> {
+        OPERATORS
+            .iter()
+            .find(|(token, op)| token == self && op.is_prefix())
+            .map(|(_, op)| op)
+    }
 
-        match *self {
-            Add | Sub => Sum,
-            Mul | Div => Product,
-            Pow => Power,
-            Func(_) => Function,
-            Equals => Assign,
-            _ => Lowest,
-        }
+    /// Returns the infix or postfix operator this Token stands for, if any.
+    pub fn infix_op(&self) -> Option<&'static Operator> {
+        OPERATORS
+            .iter()
+            .find(|(token, op)| token == self && !op.is_prefix())
+            .map(|(_, op)| op)
     }
 }
 
@@ -108,29 +300,194 @@ impl fmt::Display for Token {
             Mul => write!(f, "Mul"),
             Div => write!(f, "Div"),
             Pow => write!(f, "Pow"),
+            Mod => write!(f, "Mod"),
+            Fact => write!(f, "Fact"),
             LParen => write!(f, "("),
             RParen => write!(f, ")"),
+            Comma => write!(f, ","),
             Equals => write!(f, "="),
             // Implement Display for func
             Func(func) => write!(f, "{:?}", func),
+            Angle(mode) => write!(f, "{}", mode),
+            Convert => write!(f, "to"),
             Ident(ref s) => write!(f, "{}", s),
+            History(n) => write!(f, "${}", n),
+            Superscript(n) => write!(f, "^{}", n),
             Eof => write!(f, "Eof"),
         }
     }
 }
 
-#[derive(Debug, PartialEq, PartialOrd)]
-/// Defines all the Precedence levels, from lowest to highest.
-pub enum Precedence {
-    Lowest,
-    Sum,
-    Product,
-    Power,
-    Function,
-    Prefix,
-    Assign,
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+/// How tightly an operator binds its operands. Levels are spaced apart
+/// so that there is always room for `below`.
+pub struct Precedence(u8);
+
+impl Precedence {
+    pub const LOWEST: Precedence = Precedence(0);
+    /// Binds a unit conversion, which applies to the whole expression before it.
+    pub const CONVERT: Precedence = Precedence(5);
+    pub const SUM: Precedence = Precedence(10);
+    pub const PRODUCT: Precedence = Precedence(20);
+    pub const PREFIX: Precedence = Precedence(30);
+    pub const POWER: Precedence = Precedence(40);
+    pub const POSTFIX: Precedence = Precedence(50);
+    /// Binds the argument of a function called without parens, as in `sin x`.
+    pub const FUNCTION: Precedence = Precedence(60);
+
+    /// Returns a precedence just below this one, which a right associative
+    /// operator parses its right operand at.
+    pub fn below(self) -> Precedence {
+        Precedence(self.0 - 1)
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+/// Defines which way a chain of operators of equal precedence groups.
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`.
+    Right,
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Defines where an operator goes relative to its operands,
+/// along with how to build its `Expr`.
+pub enum Fixity {
+    Prefix(fn(Box<Expr>) -> Expr),
+    Infix(fn(Box<Expr>, Box<Expr>) -> Expr),
+    Postfix(fn(Box<Expr>) -> Expr),
 }
 
+#[derive(Debug, Copy, Clone)]
+/// An entry in the operator table.
+pub struct Operator {
+    pub precedence: Precedence,
+    pub assoc: Assoc,
+    pub fixity: Fixity,
+}
+
+impl Operator {
+    pub fn is_prefix(&self) -> bool {
+        matches!(self.fixity, Fixity::Prefix(_))
+    }
+}
+
+fn radians(e: Box<Expr>) -> Expr {
+    Expr::Angle(e, AngleMode::Radians)
+}
+
+fn degrees(e: Box<Expr>) -> Expr {
+    Expr::Angle(e, AngleMode::Degrees)
+}
+
+fn gradians(e: Box<Expr>) -> Expr {
+    Expr::Angle(e, AngleMode::Gradians)
+}
+
+/// The operator table. A new operator only needs its `Token`,
+/// the `Expr` it builds, and an entry here.
+const OPERATORS: &[(Token, Operator)] = &[
+    (
+        Token::Convert,
+        Operator {
+            precedence: Precedence::CONVERT,
+            assoc: Assoc::Left,
+            fixity: Fixity::Infix(Expr::Convert),
+        },
+    ),
+    (
+        Token::Add,
+        Operator {
+            precedence: Precedence::SUM,
+            assoc: Assoc::Left,
+            fixity: Fixity::Infix(Expr::Add),
+        },
+    ),
+    (
+        Token::Sub,
+        Operator {
+            precedence: Precedence::SUM,
+            assoc: Assoc::Left,
+            fixity: Fixity::Infix(Expr::Sub),
+        },
+    ),
+    (
+        Token::Mul,
+        Operator {
+            precedence: Precedence::PRODUCT,
+            assoc: Assoc::Left,
+            fixity: Fixity::Infix(Expr::Mul),
+        },
+    ),
+    (
+        Token::Div,
+        Operator {
+            precedence: Precedence::PRODUCT,
+            assoc: Assoc::Left,
+            fixity: Fixity::Infix(Expr::Div),
+        },
+    ),
+    (
+        Token::Mod,
+        Operator {
+            precedence: Precedence::PRODUCT,
+            assoc: Assoc::Left,
+            fixity: Fixity::Infix(Expr::Mod),
+        },
+    ),
+    // Binds looser than `^`, so that `-2^2` is `-(2^2)`.
+    (
+        Token::Sub,
+        Operator {
+            precedence: Precedence::PREFIX,
+            assoc: Assoc::Right,
+            fixity: Fixity::Prefix(Expr::Neg),
+        },
+    ),
+    (
+        Token::Pow,
+        Operator {
+            precedence: Precedence::POWER,
+            assoc: Assoc::Right,
+            fixity: Fixity::Infix(Expr::Pow),
+        },
+    ),
+    (
+        Token::Fact,
+        Operator {
+            precedence: Precedence::POSTFIX,
+            assoc: Assoc::Left,
+            fixity: Fixity::Postfix(Expr::Fact),
+        },
+    ),
+    (
+        Token::Angle(AngleMode::Radians),
+        Operator {
+            precedence: Precedence::POSTFIX,
+            assoc: Assoc::Left,
+            fixity: Fixity::Postfix(radians),
+        },
+    ),
+    (
+        Token::Angle(AngleMode::Degrees),
+        Operator {
+            precedence: Precedence::POSTFIX,
+            assoc: Assoc::Left,
+            fixity: Fixity::Postfix(degrees),
+        },
+    ),
+    (
+        Token::Angle(AngleMode::Gradians),
+        Operator {
+            precedence: Precedence::POSTFIX,
+            assoc: Assoc::Left,
+            fixity: Fixity::Postfix(gradians),
+        },
+    ),
+];
+
 #[cfg(test)]
 mod tests {
     use super::*;
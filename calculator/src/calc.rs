@@ -2,46 +2,111 @@
 //!
 //! Exposes a Calculator that calculates any given mathematical expression.
 
-use crate::eval::{eval, EvalError};
+use crate::ast::{Func, Token, FUNCTIONS};
+use crate::constants::CONSTANTS;
+use crate::eval::{
+    check_shadowing, constant, evaluate, function_names, run, EvalError, Settings, ANS,
+};
+use crate::highlight::styles;
+use crate::lexer::Lexer;
 use crate::parser::{ParseError, Parser};
 
+use serde_derive::{Deserialize, Serialize};
+
 use std::collections::HashMap;
 use std::error;
-use std::f64::consts::{E, PI};
 use std::fmt;
 
-/// A state of all defined constants/variables.
-pub type Environment = HashMap<String, f64>;
+pub use crate::ast::{AngleMode, Span};
+pub use crate::decimal::{DEFAULT_PRECISION, MAX_PRECISION};
+pub use crate::eval::Binding;
+pub use crate::highlight::Style;
+pub use crate::notation::{Notation, DEFAULT_PLACES};
+pub use crate::value::{ComplexForm, Format, FractionForm, NumberMode, Value};
+
+/// A state of all defined variables and user defined functions.
+pub type Environment = HashMap<String, Binding>;
 
 /// A log of all prior calculations and their result.
-pub type Calculations = Vec<(String, f64)>;
+pub type Calculations = Vec<(String, Value)>;
 
-#[derive(Debug)]
+/// The most changes that can be undone.
+pub const MAX_UNDO: usize = 100;
+
+#[derive(Debug, Clone)]
+/// A change to the `Environment` and the log of `Calculations`, as it is
+/// undone: applying it puts back what it holds, and gives the change that
+/// puts things back again.
+struct Change {
+    /// What was done, such as the calculation `x = 2`.
+    description: String,
+    /// Names and what they were bound to, which is `None` if they were not.
+    bindings: Vec<(String, Option<Binding>)>,
+    /// The length the log is cut back to, before `tail` is added to it.
+    log_len: usize,
+    tail: Calculations,
+}
+
+/// Returns the names that are bound differently in `after`, along with
+/// what they were bound to `before`.
+fn changed_bindings(before: &Environment, after: &Environment) -> Vec<(String, Option<Binding>)> {
+    let changed = after
+        .iter()
+        .filter(|&(name, binding)| before.get(name) != Some(binding))
+        .map(|(name, _)| (name.clone(), before.get(name).cloned()));
+    let removed = before
+        .iter()
+        .filter(|&(name, _)| !after.contains_key(name))
+        .map(|(name, binding)| (name.clone(), Some(binding.clone())));
+    changed.chain(removed).collect()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 /// A calculator that maintains a state of all prior calculations
 /// as well as the currently defined constants/variables.
 pub struct Calculator {
     env: Environment,
     calcs: Calculations,
+    settings: Settings,
+    format: Format,
+    /// Changes that can be undone and redone, the latest last. These are
+    /// not saved.
+    #[serde(skip)]
+    undo: Vec<Change>,
+    #[serde(skip)]
+    redo: Vec<Change>,
+}
+
+impl Default for Calculator {
+    fn default() -> Self {
+        Calculator::new()
+    }
 }
 
 impl Calculator {
     /// Returns a `Calculator` with a given `Environment` and prior
     /// log of `Calculations`.
     pub fn from(env: Environment, calcs: Calculations) -> Self {
-        Calculator { env, calcs }
+        Calculator {
+            env,
+            calcs,
+            settings: Settings::default(),
+            format: Format::default(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
     }
 
-    /// Returns a `Calculator` with an empty log of `Calculations`
-    /// and an `Environment` of predefined common constants.
+    /// Returns a `Calculator` with an empty log of `Calculations` and an
+    /// empty `Environment`. Common constants such as pi and e are built in.
     pub fn new() -> Self {
-        let mut env: Environment = HashMap::new();
-        env.insert("pi".to_string(), PI);
-        env.insert("e".to_string(), E);
-        Calculator { env, calcs: vec![] }
+        Calculator::from(HashMap::new(), vec![])
     }
 
-    fn balance_parens<'a>(s: &'a str) -> String {
-        let num = s.chars().fold(0, |acc, c| {
+    /// Returns how many closing parens `calculate` adds to the end of
+    /// `calc`, to close the parens left open.
+    pub fn implied_parens(calc: &str) -> usize {
+        let num = calc.chars().fold(0i32, |acc, c| {
             if c == '(' {
                 acc + 1
             } else if c == ')' {
@@ -50,64 +115,1002 @@ impl Calculator {
                 acc
             }
         });
-        if num == 0 {
-            s.to_string()
-        } else if num > 0 {
-            [s, ")".repeat(num).as_str()].concat()
-        } else {
-            s[0..s.len() - num].to_string()
+        // Unmatched closing parens are left for the parser to report.
+        num.max(0) as usize
+    }
+
+    fn balance_parens(s: &str) -> String {
+        [s, ")".repeat(Self::implied_parens(s)).as_str()].concat()
+    }
+
+    /// Returns the unit that trig functions currently take angles in.
+    pub fn angle_mode(&self) -> AngleMode {
+        self.settings.angle
+    }
+
+    /// Sets the unit that trig functions take, and inverse trig
+    /// functions return, angles in.
+    pub fn set_angle_mode(&mut self, mode: AngleMode) {
+        self.settings.angle = mode;
+    }
+
+    /// Returns the kind of numbers calculations are carried out in.
+    pub fn number_mode(&self) -> NumberMode {
+        self.settings.number
+    }
+
+    /// Sets the kind of numbers calculations are carried out in.
+    /// Values calculated in complex mode stay complex when switching back.
+    pub fn set_number_mode(&mut self, mode: NumberMode) {
+        self.settings.number = mode;
+    }
+
+    /// Returns the number of significant digits decimal mode works to.
+    pub fn precision(&self) -> u32 {
+        self.settings.precision
+    }
+
+    /// Sets the number of significant digits decimal mode works to,
+    /// which must be between 1 and `MAX_PRECISION`.
+    pub fn set_precision(&mut self, digits: u32) -> Result<(), String> {
+        if digits == 0 || digits > MAX_PRECISION {
+            return Err(format!(
+                "Precision must be between 1 and {} digits",
+                MAX_PRECISION
+            ));
         }
+        self.settings.precision = digits;
+        Ok(())
+    }
+
+    /// Returns the form complex results are written out in.
+    pub fn complex_form(&self) -> ComplexForm {
+        self.format.complex
+    }
+
+    /// Sets the form complex results are written out in.
+    pub fn set_complex_form(&mut self, form: ComplexForm) {
+        self.format.complex = form;
+    }
+
+    /// Returns the form exact fractions are written out in.
+    pub fn fraction_form(&self) -> FractionForm {
+        self.format.fraction
+    }
+
+    /// Sets the form exact fractions are written out in.
+    pub fn set_fraction_form(&mut self, form: FractionForm) {
+        self.format.fraction = form;
+    }
+
+    /// Returns the notation numbers are written out in.
+    pub fn notation(&self) -> Notation {
+        self.format.notation
+    }
+
+    /// Sets the notation numbers are written out in.
+    pub fn set_notation(&mut self, notation: Notation) {
+        self.format.notation = notation;
+    }
+
+    /// Returns the number of digits written out, which are decimal places
+    /// in fixed notation and significant digits otherwise.
+    pub fn digits(&self) -> Option<u32> {
+        self.format.digits
+    }
+
+    /// Sets the number of digits written out, or with `None`, goes back to
+    /// `DEFAULT_PLACES` decimal places in fixed notation and every digit
+    /// otherwise.
+    pub fn set_digits(&mut self, digits: Option<u32>) -> Result<(), String> {
+        if let Some(digits) = digits.filter(|&digits| digits > MAX_PRECISION) {
+            return Err(format!(
+                "Cannot write out {} digits, the most is {}",
+                digits, MAX_PRECISION
+            ));
+        }
+        self.format.digits = digits;
+        Ok(())
+    }
+
+    /// Returns whether thousands are separated by commas.
+    pub fn separators(&self) -> bool {
+        self.format.separators
+    }
+
+    /// Sets whether thousands are separated by commas, as in `1,000,000`.
+    pub fn set_separators(&mut self, separators: bool) {
+        self.format.separators = separators;
+    }
+
+    /// Writes out a result in the current `Format`, with polar angles in
+    /// the current angle mode.
+    pub fn format(&self, val: &Value) -> String {
+        val.format(self.format, self.settings.angle)
     }
 
     /// Returns the log of `Calculations`, which is just a
-    /// `Vec(String, f64)>`.
+    /// `Vec(String, Value)>`.
     pub fn get_log(&self) -> Calculations {
         self.calcs.clone()
     }
 
-    /// Takes a `&str` that represents a mathematical expression and returns the value.
-    /// Returns a `CalculatorError` in the event evaluation fails.
-    pub fn calculate<'a>(&mut self, calc: &'a str) -> Result<f64, CalculatorError> {
+    /// Returns the variables in the `Environment` and their values, sorted
+    /// by name. User defined functions are left out.
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        let mut vars: Vec<(String, Value)> = self
+            .env
+            .iter()
+            .filter_map(|(name, binding)| match binding {
+                Binding::Value(val) => Some((name.clone(), val.clone())),
+                Binding::Function(..) => None,
+            })
+            .collect();
+        vars.sort_by(|(a, _), (b, _)| a.cmp(b));
+        vars
+    }
+
+    /// Returns the user defined functions in the `Environment` and their
+    /// parameters, sorted by name.
+    pub fn functions(&self) -> Vec<(String, Vec<String>)> {
+        let mut funcs: Vec<(String, Vec<String>)> = self
+            .env
+            .iter()
+            .filter_map(|(name, binding)| match binding {
+                Binding::Function(params, _) => Some((name.clone(), params.clone())),
+                Binding::Value(_) => None,
+            })
+            .collect();
+        funcs.sort_by(|(a, _), (b, _)| a.cmp(b));
+        funcs
+    }
+
+    /// Returns the value of a variable, if it is defined.
+    pub fn get_variable(&self, name: &str) -> Option<Value> {
+        match self.env.get(name) {
+            Some(Binding::Value(val)) => Some(val.clone()),
+            _ => None,
+        }
+    }
+
+    /// Evaluates `calc` and sets the variable `name` to its value, as
+    /// `name = calc` would, but without adding to the log of `Calculations`.
+    pub fn set_variable(&mut self, name: &str, calc: &str) -> Result<Value, CalculatorError> {
+        if !is_variable_name(name) {
+            return Err(CalculatorError {
+                kind: ErrorKind::Parse,
+                message: format!("Invalid variable name: {}", name),
+                span: None,
+                token: None,
+            });
+        }
+        check_shadowing(name, self.settings, false)?;
         let calc = Self::balance_parens(calc);
-        let mut parser = Parser::new(&calc)?;
-        let val = eval(parser.parse()?, &mut self.env)?;
-        self.calcs.push((calc.to_string(), val));
+        let val = self.evaluate_value(&calc).map_err(|e| e.locate(&calc))?;
+        let old = self
+            .env
+            .insert(name.to_string(), Binding::Value(val.clone()));
+        self.record(Change {
+            description: format!("{} = {}", name, calc),
+            bindings: vec![(name.to_string(), old)],
+            log_len: self.calcs.len(),
+            tail: Vec::new(),
+        });
         Ok(val)
     }
+
+    /// Returns the names of the built in functions and constants, variables
+    /// and user defined functions that start with `prefix`, sorted, along
+    /// with `ans` once there is a result.
+    pub fn names_starting_with(&self, prefix: &str) -> Vec<String> {
+        let ans = Some(ANS).filter(|ans| !self.calcs.is_empty() && !self.env.contains_key(*ans));
+        let constants = CONSTANTS
+            .iter()
+            .map(|constant| constant.name)
+            .filter(|name| constant(name, self.settings).is_some())
+            .chain(ans);
+        let mut names: Vec<String> = FUNCTIONS
+            .iter()
+            .map(Func::name)
+            .chain(constants)
+            .map(String::from)
+            .chain(self.env.keys().cloned())
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Returns the built in constants defined in the current number mode,
+    /// each with a description and its value.
+    pub fn constants(&self) -> Vec<(&'static str, &'static str, Value)> {
+        CONSTANTS
+            .iter()
+            .filter_map(|c| constant(c.name, self.settings).map(|val| (c.name, c.description, val)))
+            .collect()
+    }
+
+    /// Returns the style of every token in `calc`, for syntax highlighting.
+    pub fn styles(&self, calc: &str) -> Vec<(Span, Style)> {
+        styles(calc, &self.env, self.settings)
+    }
+
+    /// Removes a variable, returning its value if it was defined.
+    pub fn remove_variable(&mut self, name: &str) -> Option<Value> {
+        let val = self.get_variable(name)?;
+        let old = self.env.remove(name);
+        self.record(Change {
+            description: format!("unset {}", name),
+            bindings: vec![(name.to_string(), old)],
+            log_len: self.calcs.len(),
+            tail: Vec::new(),
+        });
+        Some(val)
+    }
+
+    /// Removes a user defined function, returning its parameters if it
+    /// was defined.
+    pub fn remove_function(&mut self, name: &str) -> Option<Vec<String>> {
+        let params = match self.env.get(name) {
+            Some(Binding::Function(params, _)) => params.clone(),
+            _ => return None,
+        };
+        let old = self.env.remove(name);
+        self.record(Change {
+            description: format!("unset {}", name),
+            bindings: vec![(name.to_string(), old)],
+            log_len: self.calcs.len(),
+            tail: Vec::new(),
+        });
+        Some(params)
+    }
+
+    /// Removes every variable and user defined function, and empties the
+    /// log of `Calculations`. Settings are left as they are.
+    pub fn clear(&mut self) {
+        let bindings = self
+            .env
+            .drain()
+            .map(|(name, binding)| (name, Some(binding)))
+            .collect();
+        let calcs = std::mem::take(&mut self.calcs);
+        self.record(Change {
+            description: "clear".to_string(),
+            bindings,
+            log_len: 0,
+            tail: calcs,
+        });
+    }
+
+    /// Takes a `&str` that represents a mathematical expression and returns the value.
+    /// Function definitions such as `f(x) = x^2` have no value and return `None`.
+    /// Returns a `CalculatorError` in the event evaluation fails, which it
+    /// does for calculations that assign to built in constants.
+    pub fn calculate(&mut self, calc: &str) -> Result<Option<Value>, CalculatorError> {
+        self.calculate_with(calc, false)
+    }
+
+    /// Calculates like `calculate`, but lets variables and functions be
+    /// given the names of built in constants, which they then shadow.
+    pub fn force(&mut self, calc: &str) -> Result<Option<Value>, CalculatorError> {
+        self.calculate_with(calc, true)
+    }
+
+    fn calculate_with(
+        &mut self,
+        calc: &str,
+        force: bool,
+    ) -> Result<Option<Value>, CalculatorError> {
+        let calc = Self::balance_parens(calc);
+        let before = self.env.clone();
+        let log_len = self.calcs.len();
+        let val = Self::evaluate_in(&calc, &mut self.env, &self.calcs, self.settings, force);
+        if let Ok(Some(ref val)) = val {
+            self.calcs.push((calc.to_string(), val.clone()));
+        }
+        // Variables assigned before an error stay assigned, so even failed
+        // calculations may need undoing.
+        let bindings = changed_bindings(&before, &self.env);
+        if !bindings.is_empty() || self.calcs.len() > log_len {
+            self.record(Change {
+                description: calc.clone(),
+                bindings,
+                log_len,
+                tail: Vec::new(),
+            });
+        }
+        val.map_err(|e| e.locate(&calc))
+    }
+
+    /// Keeps the `Change` that undoes a change that has just been made,
+    /// which makes it too late to redo any changes that were undone.
+    fn record(&mut self, change: Change) {
+        self.undo.push(change);
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Makes a change, returning the change that undoes it.
+    fn apply(&mut self, change: Change) -> Change {
+        let mut undo_bindings = Vec::with_capacity(change.bindings.len());
+        for (name, binding) in change.bindings {
+            let old = match binding {
+                Some(binding) => self.env.insert(name.clone(), binding),
+                None => self.env.remove(&name),
+            };
+            undo_bindings.push((name, old));
+        }
+        let removed = self.calcs.split_off(change.log_len);
+        self.calcs.extend(change.tail);
+        Change {
+            description: change.description,
+            bindings: undo_bindings,
+            log_len: change.log_len,
+            tail: removed,
+        }
+    }
+
+    /// Undoes the last calculation, or the last change to the variables,
+    /// taking back both what it assigned or removed and its entry in the
+    /// log of `Calculations`. Returns what was undone, or `None` if there is
+    /// nothing left to undo. Settings are not undone.
+    pub fn undo(&mut self) -> Option<String> {
+        let change = self.undo.pop()?;
+        let redo = self.apply(change);
+        let description = redo.description.clone();
+        self.redo.push(redo);
+        Some(description)
+    }
+
+    /// Makes the last change that was undone again, returning what it was,
+    /// or `None` if there is nothing to redo.
+    pub fn redo(&mut self) -> Option<String> {
+        let change = self.redo.pop()?;
+        let undo = self.apply(change);
+        let description = undo.description.clone();
+        self.undo.push(undo);
+        Some(description)
+    }
+
+    /// Works out what `calculate` would return, without assigning any
+    /// variables, defining any functions or adding to the log of
+    /// `Calculations`.
+    pub fn preview(&self, calc: &str) -> Result<Option<Value>, CalculatorError> {
+        let calc = Self::balance_parens(calc);
+        let mut env = self.env.clone();
+        Self::evaluate_in(&calc, &mut env, &self.calcs, self.settings, false)
+            .map_err(|e| e.locate(&calc))
+    }
+
+    /// Evaluates `calc` against `env`, which may be a copy of the
+    /// calculator's own, with `log` for `ans` and references like `$3`.
+    /// Built in constants may only be shadowed if `force` is set.
+    fn evaluate_in(
+        calc: &str,
+        env: &mut Environment,
+        log: &[(String, Value)],
+        settings: Settings,
+        force: bool,
+    ) -> Result<Option<Value>, CalculatorError> {
+        let mut parser = Parser::new(calc)?;
+        parser.set_functions(function_names(env));
+        Ok(run(parser.parse()?, env, log, settings, force)?)
+    }
+
+    fn evaluate_value(&mut self, calc: &str) -> Result<Value, CalculatorError> {
+        let mut parser = Parser::new(calc)?;
+        parser.set_functions(function_names(&self.env));
+        Ok(evaluate(
+            parser.parse()?,
+            &mut self.env,
+            &self.calcs,
+            self.settings,
+        )?)
+    }
+}
+
+/// Returns whether `name` can be assigned to as a variable, which it can
+/// if it is read as a single identifier.
+fn is_variable_name(name: &str) -> bool {
+    let mut lexer = Lexer::new(name);
+    match lexer.next_token() {
+        Ok((Token::Ident(ref ident), _)) if ident == name => {}
+        _ => return false,
+    }
+    matches!(lexer.next_token(), Ok((Token::Eof, _)))
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+/// The stage of a calculation in which an error occurred.
+pub enum ErrorKind {
+    Lex,
+    Parse,
+    Eval,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Lex => write!(f, "lex"),
+            ErrorKind::Parse => write!(f, "parse"),
+            ErrorKind::Eval => write!(f, "eval"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 /// Defines a calculator error.
-/// Contains a `String` that describes the error.
-pub struct CalculatorError(String);
+/// Contains the kind of error, a message describing it, and, where it
+/// can be determined, the `Span` and text of the offending token.
+pub struct CalculatorError {
+    kind: ErrorKind,
+    message: String,
+    span: Option<Span>,
+    token: Option<String>,
+}
+
+impl CalculatorError {
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the byte range of the offending token in the calculation.
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Returns the text of the offending token.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Ties the error to the calculation it came from. Errors with a span
+    /// get the token text it covers; evaluation errors, which only know the
+    /// name of the variable or function they failed on, get the span of its
+    /// first occurrence.
+    fn locate(mut self, calc: &str) -> Self {
+        match self.span {
+            Some(span) => {
+                self.token = calc
+                    .get(span.start..span.end)
+                    .filter(|t| !t.is_empty())
+                    .map(|t| t.to_string());
+            }
+            None => {
+                if let Some(name) = self.token.clone() {
+                    let mut lexer = Lexer::new(calc);
+                    while let Ok((token, span)) = lexer.next_token() {
+                        if token == Token::Eof {
+                            break;
+                        }
+                        // Conversions fail on `to`, which may be written `in`.
+                        let text = calc.get(span.start..span.end);
+                        if text == Some(name.as_str()) || token.to_string() == name {
+                            self.span = Some(span);
+                            self.token = text.map(|t| t.to_string());
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self
+    }
+}
 
 impl fmt::Display for CalculatorError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return write!(f, "{}", self.0);
+        write!(f, "{}", self.message)
     }
 }
 
 impl error::Error for CalculatorError {
     fn description(&self) -> &str {
-        &self.0
+        &self.message
     }
 }
 
 impl From<ParseError> for CalculatorError {
     fn from(err: ParseError) -> Self {
-        match err {
-            ParseError::ExpectErr(s) => CalculatorError(s),
-            ParseError::UnknownAtom(s) => CalculatorError(s),
-            ParseError::InvalidInput(s) => CalculatorError(s),
-            ParseError::NoneError(s) => CalculatorError(s),
+        let kind = match err {
+            ParseError::LexErr(_) => ErrorKind::Lex,
+            _ => ErrorKind::Parse,
+        };
+        CalculatorError {
+            kind,
+            message: err.to_string(),
+            span: Some(err.span()),
+            token: None,
         }
     }
 }
 
 impl From<EvalError> for CalculatorError {
     fn from(err: EvalError) -> Self {
-        match err {
-            EvalError::UnknownVar(s) => CalculatorError(s),
+        let token = match err {
+            EvalError::UnknownVar(ref name)
+            | EvalError::WrongArity(ref name, ..)
+            | EvalError::NotAFunction(ref name)
+            | EvalError::NotAValue(ref name)
+            | EvalError::RecursionLimit(ref name)
+            | EvalError::NotReal(ref name)
+            | EvalError::NoResult(ref name, _)
+            | EvalError::ZeroResult(ref name)
+            | EvalError::Constant(ref name)
+            | EvalError::TooManyPlaces(ref name) => Some(name.clone()),
+            EvalError::Units(ref e) => e.op().map(|op| op.to_string()),
+        };
+        CalculatorError {
+            kind: ErrorKind::Eval,
+            message: err.to_string(),
+            span: None,
+            token,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn parse_error() {
+        let err = Calculator::new().calculate("3 + * 4").unwrap_err();
+        assert_eq!(ErrorKind::Parse, err.kind());
+        assert_eq!(Some(Span::new(4, 5)), err.span());
+        assert_eq!(Some("*"), err.token());
+    }
+
+    #[test]
+    fn lex_error() {
+        let err = Calculator::new().calculate("2 + 3 & 4").unwrap_err();
+        assert_eq!(ErrorKind::Lex, err.kind());
+        assert_eq!(Some(Span::new(6, 7)), err.span());
+        assert_eq!(Some("&"), err.token());
+    }
+
+    #[test]
+    fn eval_error() {
+        let err = Calculator::new().calculate("2pi + y").unwrap_err();
+        assert_eq!(ErrorKind::Eval, err.kind());
+        assert_eq!(Some(Span::new(6, 7)), err.span());
+        assert_eq!(Some("y"), err.token());
+    }
+
+    #[test]
+    fn conversion_error() {
+        let err = Calculator::new().calculate("3 in km").unwrap_err();
+        assert_eq!(ErrorKind::Eval, err.kind());
+        assert_eq!(Some(Span::new(2, 4)), err.span());
+        assert_eq!(Some("in"), err.token());
+
+        let err = Calculator::new().calculate("2 kg to m").unwrap_err();
+        assert_eq!(Some(Span::new(5, 7)), err.span());
+    }
+
+    #[test]
+    fn arity_error() {
+        let err = Calculator::new().calculate("1 + atan2(1)").unwrap_err();
+        assert_eq!(ErrorKind::Eval, err.kind());
+        assert_eq!(Some(Span::new(4, 9)), err.span());
+    }
+
+    #[test]
+    fn preview() {
+        let mut calc = Calculator::new();
+        calc.calculate("x = 2").unwrap();
+        assert_eq!(Some(Value::Real(6.0)), calc.preview("x = 3x").unwrap());
+        assert_eq!(None, calc.preview("f(y) = y").unwrap());
+        assert!(calc.preview("3 +").is_err());
+        assert_eq!(Some(Value::Real(2.0)), calc.get_variable("x"));
+        assert!(calc.functions().is_empty());
+        assert_eq!(1, calc.get_log().len());
+    }
+
+    #[test]
+    fn names() {
+        let mut calc = Calculator::new();
+        calc.calculate("arc = 1").unwrap();
+        calc.calculate("f(x) = x").unwrap();
+        let names = calc.names_starting_with("arc");
+        assert_eq!(vec!["arc", "arccos", "arcsin", "arctan"], names);
+        assert_eq!(vec!["f", "floor"], calc.names_starting_with("f"));
+        // `i` is only a constant in complex mode.
+        assert!(calc.names_starting_with("i").is_empty());
+        calc.set_number_mode(NumberMode::Complex);
+        assert_eq!(vec!["i"], calc.names_starting_with("i"));
+        assert_eq!(
+            vec!["abs", "ans", "arc"],
+            calc.names_starting_with("a")[..3].to_vec()
+        );
+    }
+
+    #[test]
+    fn earlier_results() {
+        let mut calc = Calculator::new();
+        let err = calc.calculate("ans + 1").unwrap_err();
+        assert_eq!("No result ans: there are no results yet", err.message());
+        assert_eq!(Some(Span::new(0, 3)), err.span());
+
+        calc.calculate("2 + 3").unwrap();
+        calc.calculate("f(x) = 10x").unwrap();
+        assert_eq!(Some(Value::Real(6.0)), calc.calculate("ans + 1").unwrap());
+        assert_eq!(Some(Value::Real(10.0)), calc.preview("2$1").unwrap());
+        assert_eq!(Some(Value::Real(60.0)), calc.calculate("f(_2)").unwrap());
+        assert_eq!(
+            Some(Value::Real(66.0)),
+            calc.calculate("$-1 + $-2").unwrap()
+        );
+        let err = calc.calculate("$9").unwrap_err();
+        assert_eq!("No result $9: there are only 4 results", err.message());
+        assert_eq!(Some(Span::new(0, 2)), err.span());
+        // Errors name the reference as it was written.
+        let err = calc.calculate("1 + _9").unwrap_err();
+        assert_eq!("No result _9: there are only 4 results", err.message());
+        assert_eq!(Some(Span::new(4, 6)), err.span());
+        let err = calc.calculate("$99999999999999999999").unwrap_err();
+        assert_eq!(
+            "No result $99999999999999999999: there are only 4 results",
+            err.message()
+        );
+        assert_eq!(Some(Span::new(0, 21)), err.span());
+        let err = calc.calculate("$0").unwrap_err();
+        assert_eq!("No result $0: results are numbered from $1", err.message());
+
+        // A variable named ans takes the place of the last result.
+        calc.calculate("ans = 1").unwrap();
+        assert_eq!(Some(Value::Real(2.0)), calc.calculate("ans + 1").unwrap());
+    }
+
+    #[test]
+    fn constants() {
+        let mut calc = Calculator::new();
+        let err = calc.calculate("pi = 3").unwrap_err();
+        assert_eq!("pi is a built in constant", err.message());
+        calc.calculate("e(x) = x").unwrap_err();
+        calc.set_variable("c", "1").unwrap_err();
+        assert!(calc.variables().is_empty() && calc.functions().is_empty());
+        // Parameters may still take their names.
+        calc.calculate("f(c) = 2c").unwrap();
+        assert_eq!(Some(Value::Real(6.0)), calc.calculate("f(3)").unwrap());
+
+        let light = calc.calculate("c * 2 s").unwrap().unwrap();
+        assert_eq!("599584916 m", calc.format(&light));
+        let gas = calc.calculate("R / (k_B * N_A)").unwrap().unwrap();
+        assert_eq!("1", calc.format(&gas));
+        // Every constant but i is defined outside complex mode.
+        let names: Vec<&str> = calc.constants().iter().map(|c| c.0).collect();
+        assert_eq!(CONSTANTS.len() - 1, names.len());
+        assert!(!names.contains(&"i"));
+
+        calc.set_number_mode(NumberMode::Decimal);
+        let phi = calc.calculate("phi").unwrap().unwrap();
+        assert!(calc.format(&phi).starts_with("1.6180339887498948482"));
+        // Constants are worked out again at a new precision.
+        calc.set_precision(60).unwrap();
+        match calc.calculate("phi").unwrap() {
+            Some(Value::Decimal(phi)) => assert_eq!(60, phi.precision()),
+            other => panic!("expected a decimal, got {:?}", other),
+        }
+
+        calc.set_number_mode(NumberMode::Real);
+        assert_eq!(Some(Value::Real(3.0)), calc.force("pi = 3").unwrap());
+        assert_eq!(Some(Value::Real(6.0)), calc.calculate("2pi").unwrap());
+        calc.remove_variable("pi");
+        assert_eq!(Some(Value::Real(2.0 * PI)), calc.calculate("2pi").unwrap());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut calc = Calculator::new();
+        calc.calculate("f(x, y) = x^2 + y").unwrap();
+        calc.calculate("a = f(2, 1) * 3 km").unwrap();
+        calc.set_number_mode(NumberMode::Exact);
+        calc.calculate("b = 1/3").unwrap();
+        calc.set_number_mode(NumberMode::Decimal);
+        calc.calculate("w = 2/3").unwrap();
+        calc.set_number_mode(NumberMode::Complex);
+        calc.calculate("d = 1 + 2i").unwrap();
+        calc.set_fraction_form(FractionForm::Mixed);
+
+        let saved = serde_json::to_string(&calc).unwrap();
+        let mut loaded: Calculator = serde_json::from_str(&saved).unwrap();
+        assert_eq!(calc.variables(), loaded.variables());
+        assert_eq!(calc.get_log(), loaded.get_log());
+        assert_eq!(NumberMode::Complex, loaded.number_mode());
+        assert_eq!(FractionForm::Mixed, loaded.fraction_form());
+        assert_eq!(
+            vec![("f".to_string(), vec!["x".to_string(), "y".to_string()])],
+            loaded.functions()
+        );
+        assert_eq!(
+            Some(Value::Real(10.0)),
+            loaded.calculate("f(3, 1)").unwrap()
+        );
+        assert_eq!("15 km", loaded.format(&loaded.get_variable("a").unwrap()));
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut calc = Calculator::new();
+        assert_eq!(None, calc.undo());
+        calc.calculate("x = 2").unwrap();
+        calc.force("pi = 3").unwrap();
+        assert_eq!(Some("pi = 3".to_string()), calc.undo());
+        assert_eq!(Some(Value::Real(PI)), calc.preview("pi").unwrap());
+        assert_eq!(1, calc.get_log().len());
+
+        assert_eq!(Some("pi = 3".to_string()), calc.redo());
+        assert_eq!(Some(Value::Real(3.0)), calc.get_variable("pi"));
+        assert_eq!(2, calc.get_log().len());
+        assert_eq!(None, calc.redo());
+
+        calc.clear();
+        calc.set_variable("y", "x").unwrap_err();
+        assert_eq!(Some("clear".to_string()), calc.undo());
+        assert_eq!(Some(Value::Real(2.0)), calc.get_variable("x"));
+        assert_eq!(2, calc.get_log().len());
+
+        // A new change cannot be redone past.
+        calc.undo();
+        calc.calculate("f(t) = t").unwrap();
+        assert_eq!(None, calc.redo());
+        assert_eq!(Some("f(t) = t".to_string()), calc.undo());
+        assert!(calc.functions().is_empty());
+        calc.remove_variable("x");
+        calc.undo();
+        assert_eq!(Some(Value::Real(2.0)), calc.get_variable("x"));
+
+        for _ in 0..MAX_UNDO + 10 {
+            calc.calculate("1").unwrap();
+        }
+        let mut undone = 0;
+        while calc.undo().is_some() {
+            undone += 1;
+        }
+        assert_eq!(MAX_UNDO, undone);
+    }
+
+    #[test]
+    fn user_functions() {
+        let mut calc = Calculator::new();
+        assert_eq!(None, calc.calculate("area(r) = pi * r^2").unwrap());
+        assert_eq!(
+            Some(Value::Real(4.0 * PI)),
+            calc.calculate("area(2)").unwrap()
+        );
+        assert_eq!(
+            Some(Value::Real(5.0 * PI)),
+            calc.calculate("area(1) + area(2)").unwrap()
+        );
+        assert_eq!(2, calc.get_log().len());
+        calc.calculate("b = 2").unwrap();
+        calc.calculate("a = area(1)").unwrap();
+        let names: Vec<String> = calc.variables().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(vec!["a", "b"], names);
+
+        // A function may be defined before the functions it calls.
+        assert_eq!(None, calc.calculate("g(x) = f(x) + 1").unwrap());
+        assert_eq!(ErrorKind::Eval, calc.calculate("g(1)").unwrap_err().kind());
+        calc.calculate("f(x) = 2x").unwrap();
+        assert_eq!(Some(Value::Real(7.0)), calc.calculate("g(3)").unwrap());
+        let err = calc.calculate("h(x) = b(x + 1)").unwrap_err();
+        assert_eq!("b is not a function", err.to_string());
+    }
+
+    #[test]
+    fn variables() {
+        let mut calc = Calculator::new();
+        calc.calculate("f(x) = 2x").unwrap();
+        assert_eq!(Value::Real(6.0), calc.set_variable("y", "f(3)").unwrap());
+        assert_eq!(Some(Value::Real(6.0)), calc.get_variable("y"));
+        assert_eq!(None, calc.get_variable("f"));
+        assert!(calc.get_log().is_empty());
+
+        assert!(calc.set_variable("2x", "1").is_err());
+        assert!(calc.set_variable("sin", "1").is_err());
+        let err = calc.set_variable("z", "(1 +").unwrap_err();
+        assert_eq!(ErrorKind::Parse, err.kind());
+        let err = calc.set_variable("z", "f").unwrap_err();
+        assert_eq!(ErrorKind::Eval, err.kind());
+        assert_eq!(None, calc.get_variable("z"));
+
+        assert_eq!(None, calc.remove_variable("f"));
+        assert_eq!(Some(Value::Real(6.0)), calc.remove_variable("y"));
+        assert_eq!(None, calc.get_variable("y"));
+        assert_eq!(None, calc.remove_function("y"));
+        assert_eq!(Some(vec!["x".to_string()]), calc.remove_function("f"));
+        assert!(calc.functions().is_empty());
+        assert_eq!(Some("unset f".to_string()), calc.undo());
+        assert_eq!(Some(Value::Real(6.0)), calc.calculate("f(3)").unwrap());
+
+        calc.calculate("x = 2").unwrap();
+        calc.clear();
+        assert!(calc.variables().is_empty());
+        assert!(calc.get_log().is_empty());
+        assert!(calc.calculate("f(1)").is_err());
+    }
+
+    #[test]
+    fn variable_names() {
+        let mut calc = Calculator::new();
+        calc.calculate("x = 2").unwrap();
+        calc.calculate("x1 = 3").unwrap();
+        calc.calculate("v_0 = 5").unwrap();
+        calc.calculate("θ = 30").unwrap();
+        assert_eq!(Some(Value::Real(6.0)), calc.calculate("2x1").unwrap());
+        assert_eq!(Some(Value::Real(12.0)), calc.calculate("x1(4)").unwrap());
+        assert_eq!(Some(Value::Real(6.0)), calc.calculate("x1 2").unwrap());
+        assert_eq!(Some(Value::Real(4.0)), calc.calculate("x 2").unwrap());
+        assert_eq!(Some(Value::Real(25.0)), calc.calculate("v_0^2").unwrap());
+        assert_eq!(
+            Some(Value::Real(0.5)),
+            calc.calculate("round(sin(θ deg), 9)").unwrap()
+        );
+        // Digits after a name are part of it, rather than multiplying it.
+        let err = calc.calculate("x2").unwrap_err();
+        assert_eq!("Unknown variable: x2", err.message());
+        assert_eq!(Some(Value::Real(0.0)), calc.calculate("sin0").unwrap());
+        // An underscore on its own starts a reference to an earlier result.
+        assert_eq!(Some(Value::Real(9.0)), calc.calculate("x1*_2").unwrap());
+        assert!(calc.set_variable("rate_2024", "1.05").is_ok());
+        assert!(calc.set_variable("x_", "1").is_err());
+    }
+
+    #[test]
+    fn unicode_symbols() {
+        let mut calc = Calculator::new();
+        calc.calculate("r = 2").unwrap();
+        assert_eq!(Some(Value::Real(5.0)), calc.calculate("2×3 − 4÷4").unwrap());
+        assert_eq!(Some(Value::Real(4.0 * PI)), calc.calculate("π·r²").unwrap());
+        assert_eq!(Some(Value::Real(3.0)), calc.calculate("√9").unwrap());
+        assert_eq!(Some(Value::Real(0.125)), calc.calculate("r⁻³").unwrap());
+        let area = calc.calculate("(3 m)² to cm²").unwrap().unwrap();
+        assert_eq!("90000 cm^2", calc.format(&area));
+        let err = calc.calculate("r ≤ 3").unwrap_err();
+        assert_eq!(ErrorKind::Lex, err.kind());
+    }
+
+    #[test]
+    fn angle_mode() {
+        let mut calc = Calculator::new();
+        assert_eq!(AngleMode::Radians, calc.angle_mode());
+        calc.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(Some(Value::Real(0.0)), calc.calculate("cos(90)").unwrap());
+        assert_eq!(
+            Some(Value::Real(180.0)),
+            calc.calculate("arccos(-1)").unwrap()
+        );
+        assert_eq!(Some(Value::Real(180.0)), calc.calculate("pi rad").unwrap());
+    }
+
+    #[test]
+    fn unmatched_close_paren() {
+        let err = Calculator::new().calculate("(1 + 2))").unwrap_err();
+        assert_eq!(ErrorKind::Parse, err.kind());
+        assert_eq!(Some(Span::new(7, 8)), err.span());
+    }
+
+    #[test]
+    fn implied_parens() {
+        assert_eq!(2, Calculator::implied_parens("9sin(pi/(2"));
+        assert_eq!(0, Calculator::implied_parens("(1 + 2))"));
+    }
+
+    #[test]
+    fn complex_mode() {
+        let mut calc = Calculator::new();
+        calc.set_number_mode(NumberMode::Complex);
+        let val = calc.calculate("sqrt(-4) + 1").unwrap().unwrap();
+        assert_eq!("1 + 2i", calc.format(&val));
+
+        calc.set_complex_form(ComplexForm::Polar);
+        calc.set_angle_mode(AngleMode::Degrees);
+        let val = calc.calculate("2i").unwrap().unwrap();
+        assert_eq!("2 ∠ 90 deg", calc.format(&val));
+
+        let err = calc.calculate("3 + i!").unwrap_err();
+        assert_eq!(ErrorKind::Eval, err.kind());
+        assert_eq!("! is not defined for complex numbers", err.message());
+        assert_eq!(Some(Span::new(5, 6)), err.span());
+    }
+
+    #[test]
+    fn exact_mode() {
+        let mut calc = Calculator::new();
+        calc.set_number_mode(NumberMode::Exact);
+        let val = calc.calculate("x = 1/3 + 7/6").unwrap().unwrap();
+        assert_eq!("3/2", calc.format(&val));
+        calc.set_fraction_form(FractionForm::Mixed);
+        assert_eq!("1 1/2", calc.format(&val));
+        // Constants are floats, so anything using them is too.
+        let val = calc.calculate("x * pi / pi").unwrap().unwrap();
+        assert_eq!("1.5", calc.format(&val));
+        // Only complex mode has complex results.
+        for calc_str in &["sqrt(-1)", "ln(-2)", "(-1)^0.5"] {
+            let val = calc.calculate(calc_str).unwrap().unwrap();
+            assert!(val.as_real().is_some_and(f64::is_nan), "{}", calc_str);
+        }
+        let val = calc.calculate("round(1/3, 5)").unwrap().unwrap();
+        assert_eq!("33333/100000", calc.format(&val));
+        for calc_str in &["round(1/3, 3000000)", "round(1/3, -40000)"] {
+            let err = calc.calculate(calc_str).unwrap_err();
+            assert_eq!(
+                "round can only round to 32768 decimal places",
+                err.message()
+            );
+        }
+    }
+
+    #[test]
+    fn decimal_mode() {
+        let mut calc = Calculator::new();
+        calc.set_number_mode(NumberMode::Decimal);
+        calc.set_precision(40).unwrap();
+        let val = calc.calculate("pi").unwrap().unwrap();
+        assert_eq!(
+            "3.141592653589793238462643383279502884197",
+            calc.format(&val)
+        );
+        let val = calc.calculate("1/3 + 1/6").unwrap().unwrap();
+        assert_eq!("0.5", calc.format(&val));
+        let val = calc.calculate("sqrt(2)").unwrap().unwrap();
+        assert_eq!(
+            "1.41421356237309504880168872420969807857",
+            calc.format(&val)
+        );
+        calc.set_angle_mode(AngleMode::Degrees);
+        let val = calc.calculate("sin(30) + cos(90)").unwrap().unwrap();
+        assert_eq!("0.5", calc.format(&val));
+        let val = calc.calculate("arcsin(0.5)").unwrap().unwrap();
+        assert_eq!("30", calc.format(&val));
+        let val = calc.calculate("ln(e^3)").unwrap().unwrap();
+        assert_eq!("3", calc.format(&val));
+        let val = calc.calculate("25!").unwrap().unwrap();
+        assert_eq!("15511210043330985984000000", calc.format(&val));
+        // Only complex mode has complex results.
+        for calc_str in &["sqrt(-1)", "ln(-1)", "(-1)^0.5"] {
+            let val = calc.calculate(calc_str).unwrap().unwrap();
+            assert!(val.as_real().is_some_and(f64::is_nan), "{}", calc_str);
+        }
+        // Results too large for a decimal overflow to infinity, as floats do.
+        calc.calculate("x = 10^(10^18)").unwrap();
+        for calc_str in &["10^(5*10^18)", "x*x*x", "-x*x*x - 1"] {
+            let val = calc.calculate(calc_str).unwrap().unwrap();
+            assert!(val.as_real().is_some_and(f64::is_infinite), "{}", calc_str);
+        }
+
+        assert!(calc.set_precision(0).is_err());
+        assert_eq!(40, calc.precision());
+    }
+
+    #[test]
+    fn notation() {
+        let mut calc = Calculator::new();
+        let val = calc.calculate("2/3 * 1000 m").unwrap().unwrap();
+        calc.set_notation(Notation::Fixed);
+        calc.set_digits(Some(2)).unwrap();
+        assert_eq!("666.67 m", calc.format(&val));
+        calc.set_notation(Notation::Engineering);
+        calc.set_digits(None).unwrap();
+        assert_eq!("666.6666666666666e0 m", calc.format(&val));
+        assert!(calc.set_digits(Some(MAX_PRECISION + 1)).is_err());
+        calc.set_notation(Notation::Si);
+        calc.set_digits(Some(3)).unwrap();
+        let val = calc.calculate("12345.6 * 100").unwrap().unwrap();
+        assert_eq!("1.23M", calc.format(&val));
+        calc.set_notation(Notation::Fixed);
+        calc.set_separators(true);
+        assert_eq!("1,234,560.000", calc.format(&val));
+        calc.set_number_mode(NumberMode::Decimal);
+        let val = calc.calculate("10^(10^15)").unwrap().unwrap();
+        assert_eq!("1.00e1000000000000000", calc.format(&val));
+    }
+}
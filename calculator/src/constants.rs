@@ -0,0 +1,91 @@
+//! # Constants
+//!
+//! The built in constants, which are kept apart from the variables of an
+//! `Environment` and cannot be assigned to.
+
+/// How the value of a constant is worked out.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Definition {
+    Pi,
+    E,
+    /// The imaginary unit, which is only defined in complex mode.
+    I,
+    /// A calculation, evaluated in the number mode of the time, so that
+    /// mathematical constants are worked out to the precision of decimal
+    /// mode and physical ones have their units.
+    Calc(&'static str),
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+/// A built in constant, as listed by `Calculator::constants`.
+pub struct Constant {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub definition: Definition,
+}
+
+const fn calc(name: &'static str, description: &'static str, calc: &'static str) -> Constant {
+    Constant {
+        name,
+        description,
+        definition: Definition::Calc(calc),
+    }
+}
+
+/// Every built in constant. Physical constants have their 2018 CODATA
+/// values. Names already taken by units, such as `h` for the hour, are
+/// left to the units, so Planck's constant is `planck`.
+pub const CONSTANTS: &[Constant] = &[
+    Constant {
+        name: "pi",
+        description: "Ratio of a circle's circumference to its diameter",
+        definition: Definition::Pi,
+    },
+    calc(
+        "tau",
+        "Ratio of a circle's circumference to its radius",
+        "2pi",
+    ),
+    Constant {
+        name: "e",
+        description: "Base of the natural logarithm",
+        definition: Definition::E,
+    },
+    calc("phi", "Golden ratio", "(1 + sqrt(5))/2"),
+    Constant {
+        name: "i",
+        description: "Imaginary unit, in complex mode",
+        definition: Definition::I,
+    },
+    calc("c", "Speed of light in vacuum", "299792458 m/s"),
+    calc(
+        "G",
+        "Newtonian constant of gravitation",
+        "6.67430e-11 m^3/(kg*s^2)",
+    ),
+    calc("g_n", "Standard acceleration of gravity", "9.80665 m/s^2"),
+    calc("planck", "Planck constant", "6.62607015e-34 J*s"),
+    calc("hbar", "Reduced Planck constant", "planck/(2pi)"),
+    calc("k_B", "Boltzmann constant", "1.380649e-23 J/K"),
+    calc("N_A", "Avogadro constant", "6.02214076e23/mol"),
+    calc("R", "Molar gas constant", "k_B*N_A"),
+    calc("q_e", "Elementary charge", "1.602176634e-19 C"),
+    calc("m_e", "Electron mass", "9.1093837015e-31 kg"),
+    calc("m_p", "Proton mass", "1.67262192369e-27 kg"),
+    calc(
+        "mu_0",
+        "Vacuum magnetic permeability",
+        "1.25663706212e-6 N/A^2",
+    ),
+    calc("epsilon_0", "Vacuum electric permittivity", "1/(mu_0*c^2)"),
+    calc(
+        "sigma",
+        "Stefan-Boltzmann constant",
+        "5.670374419e-8 W/(m^2*K^4)",
+    ),
+];
+
+/// Looks up a built in constant by name.
+pub fn find(name: &str) -> Option<&'static Constant> {
+    CONSTANTS.iter().find(|constant| constant.name == name)
+}
@@ -0,0 +1,679 @@
+//! # Decimal
+//!
+//! Arbitrary-precision decimal numbers and the functions on them used by
+//! decimal mode.
+
+use crate::ast::AngleMode;
+
+use num_bigint::{BigInt, Sign};
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use serde_derive::{Deserialize, Serialize};
+
+use std::cmp::Ordering;
+use std::f64::consts::LN_10;
+use std::fmt;
+
+/// The number of significant digits decimal mode works to unless told otherwise.
+pub const DEFAULT_PRECISION: u32 = 50;
+
+/// The most significant digits decimal mode can be asked to work to.
+pub const MAX_PRECISION: u32 = 1000;
+
+/// Extra digits functions work to, so that rounding errors along the way
+/// do not reach the digits that are kept.
+pub const GUARD_DIGITS: u32 = 10;
+
+/// The largest exponent a result can have. This leaves room to add the
+/// exponents of two numbers, and the digits of their mantissas, without
+/// overflowing, so operations check their results against it and give
+/// `None` past it.
+const MAX_EXP: i64 = i64::MAX / 4;
+
+/// Splits a float into the digits and exponent of the shortest decimal that
+/// reads back as it, so that `0.1` gives `(1, -1)`. It must be finite.
+pub fn decimal_parts(x: f64) -> (BigInt, i64) {
+    // Formats as, for example, `-1.25e-3`.
+    let literal = format!("{:e}", x);
+    let (mantissa, exp) = literal.split_at(literal.find('e').unwrap());
+    let decimals = mantissa.find('.').map_or(0, |dot| mantissa.len() - dot - 1);
+    let digits = mantissa.replace('.', "").parse().unwrap();
+    (digits, exp[1..].parse::<i64>().unwrap() - decimals as i64)
+}
+
+fn pow10(n: u32) -> BigInt {
+    BigInt::from(10).pow(n)
+}
+
+fn digit_count(n: &BigInt) -> u32 {
+    n.abs().to_string().len() as u32
+}
+
+/// Returns `n / d` rounded to the nearest integer, with halves rounded
+/// away from zero.
+fn round_div(n: &BigInt, d: &BigInt) -> BigInt {
+    let (q, r) = n.div_rem(d);
+    if r.abs() * 2 >= d.abs() {
+        q + n.signum() * d.signum()
+    } else {
+        q
+    }
+}
+
+/// Returns `atan(1/n)` as a fixed point number with `unity` as one.
+fn atan_inv(n: u32, unity: &BigInt) -> BigInt {
+    let n2 = BigInt::from(n * n);
+    let mut power = unity / n;
+    let mut sum = power.clone();
+    for k in 1u32.. {
+        power /= &n2;
+        if power.is_zero() {
+            break;
+        }
+        let term = &power / (2 * k + 1);
+        if k % 2 == 1 {
+            sum -= term;
+        } else {
+            sum += term;
+        }
+    }
+    sum
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A decimal number, `mantissa * 10^exp`, rounded to `precision`
+/// significant digits. Trailing zeros are kept out of the mantissa, so
+/// that integers are exactly the numbers with a non-negative `exp`.
+pub struct Decimal {
+    mantissa: BigInt,
+    exp: i64,
+    precision: u32,
+}
+
+impl Decimal {
+    fn new(mut mantissa: BigInt, mut exp: i64, precision: u32) -> Self {
+        let digits = digit_count(&mantissa);
+        if digits > precision {
+            let drop = digits - precision;
+            mantissa = round_div(&mantissa, &pow10(drop));
+            exp += i64::from(drop);
+        }
+        if mantissa.is_zero() {
+            exp = 0;
+        } else {
+            let ten = BigInt::from(10);
+            while mantissa.is_multiple_of(&ten) {
+                mantissa /= &ten;
+                exp += 1;
+            }
+        }
+        Decimal {
+            mantissa,
+            exp,
+            precision,
+        }
+    }
+
+    /// Returns the number, or `None` if its exponent is past `MAX_EXP`.
+    fn checked(mantissa: BigInt, exp: i64, precision: u32) -> Option<Self> {
+        Some(Decimal::new(mantissa, exp, precision)).filter(|d| d.exp.abs() <= MAX_EXP)
+    }
+
+    /// Returns the value of a number literal to `precision` digits, or
+    /// `None` if it is infinite or NaN. See `decimal_parts`.
+    pub fn from_f64(x: f64, precision: u32) -> Option<Self> {
+        if !x.is_finite() {
+            return None;
+        }
+        let (digits, exp) = decimal_parts(x);
+        Some(Decimal::new(digits, exp, precision))
+    }
+
+    pub fn from_integer(n: BigInt, precision: u32) -> Self {
+        Decimal::new(n, 0, precision)
+    }
+
+    pub fn from_i64(n: i64, precision: u32) -> Self {
+        Decimal::from_integer(BigInt::from(n), precision)
+    }
+
+    pub fn from_rational(r: &BigRational, precision: u32) -> Self {
+        let (numer, denom) = (r.numer(), r.denom());
+        let shift = i64::from(precision) + 2 + i64::from(digit_count(denom))
+            - i64::from(digit_count(numer));
+        let shift = shift.max(0) as u32;
+        let mantissa = round_div(&(numer * pow10(shift)), denom);
+        Decimal::new(mantissa, -i64::from(shift), precision)
+    }
+
+    /// Returns pi to `precision` digits, using Machin's formula.
+    pub fn pi(precision: u32) -> Self {
+        let wp = precision + GUARD_DIGITS;
+        let unity = pow10(wp);
+        let pi = (atan_inv(5, &unity) * 4 - atan_inv(239, &unity)) * 4;
+        Decimal::new(pi, -i64::from(wp), precision)
+    }
+
+    /// Returns e to `precision` digits.
+    pub fn e(precision: u32) -> Self {
+        Decimal::from_i64(1, precision).exp().unwrap()
+    }
+
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    /// Returns this number rounded to a different number of digits.
+    pub fn with_precision(&self, precision: u32) -> Self {
+        Decimal::new(self.mantissa.clone(), self.exp, precision)
+    }
+
+    /// Returns the mantissa and exponent the number is made of.
+    pub fn parts(&self) -> (&BigInt, i64) {
+        (&self.mantissa, self.exp)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        format!("{}e{}", self.mantissa, self.exp).parse().unwrap()
+    }
+
+    /// Returns the number if it is an integer that fits in an `i64`.
+    pub fn to_i64(&self) -> Option<i64> {
+        if !self.is_integer() || self.exp > 18 {
+            return None;
+        }
+        (&self.mantissa * pow10(self.exp as u32)).to_i64()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.mantissa.is_negative()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.exp >= 0
+    }
+
+    /// Returns the power of ten of the leading digit, so 0 for `1.5`
+    /// and -2 for `0.015`.
+    fn magnitude(&self) -> i64 {
+        self.exp + i64::from(digit_count(&self.mantissa)) - 1
+    }
+
+    pub fn abs(&self) -> Self {
+        Decimal::new(self.mantissa.abs(), self.exp, self.precision)
+    }
+
+    pub fn neg(&self) -> Self {
+        Decimal::new(-&self.mantissa, self.exp, self.precision)
+    }
+
+    /// The sum, and the results of other operations on two numbers, are
+    /// rounded to the greater of their precisions. They are `None` if the
+    /// result is too large or small to hold.
+    pub fn add(&self, other: &Decimal) -> Option<Self> {
+        let precision = self.precision.max(other.precision);
+        let (big, small) = if self.abs() >= other.abs() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        // A term too small to change the kept digits is left out, rather than
+        // lining it up with a mantissa of millions of digits.
+        if small.is_zero() || big.magnitude() - small.magnitude() > i64::from(precision) + 2 {
+            return Some(big.with_precision(precision));
+        }
+        let exp = self.exp.min(other.exp);
+        let lhs = &self.mantissa * pow10((self.exp - exp) as u32);
+        let rhs = &other.mantissa * pow10((other.exp - exp) as u32);
+        Decimal::checked(lhs + rhs, exp, precision)
+    }
+
+    pub fn sub(&self, other: &Decimal) -> Option<Self> {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Decimal) -> Option<Self> {
+        let precision = self.precision.max(other.precision);
+        let mantissa = &self.mantissa * &other.mantissa;
+        Decimal::checked(mantissa, self.exp + other.exp, precision)
+    }
+
+    /// Returns the quotient, or `None` when dividing by zero or the
+    /// quotient is out of range.
+    pub fn div(&self, other: &Decimal) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        let precision = self.precision.max(other.precision);
+        let shift = i64::from(precision) + 2 + i64::from(digit_count(&other.mantissa))
+            - i64::from(digit_count(&self.mantissa));
+        let shift = shift.max(0) as u32;
+        let mantissa = round_div(&(&self.mantissa * pow10(shift)), &other.mantissa);
+        let exp = self.exp - other.exp - i64::from(shift);
+        Decimal::checked(mantissa, exp, precision)
+    }
+
+    /// Rounds to an integer with `op`, which divides its first argument by
+    /// its second, a power of ten.
+    fn integral(&self, op: fn(&BigInt, &BigInt) -> BigInt) -> Self {
+        if self.is_integer() {
+            return self.clone();
+        }
+        // Anything smaller than 0.1 rounds the same way as ±0.01 does,
+        // which keeps the power of ten small.
+        let (mantissa, exp) = if self.magnitude() < -1 {
+            (self.mantissa.signum(), -2)
+        } else {
+            (self.mantissa.clone(), self.exp)
+        };
+        Decimal::new(op(&mantissa, &pow10(-exp as u32)), 0, self.precision)
+    }
+
+    pub fn floor(&self) -> Self {
+        self.integral(|n, d| n.div_floor(d))
+    }
+
+    pub fn ceil(&self) -> Self {
+        self.neg().floor().neg()
+    }
+
+    pub fn trunc(&self) -> Self {
+        self.integral(|n, d| n / d)
+    }
+
+    /// Rounds to the nearest integer, with halves rounded away from zero.
+    pub fn round(&self) -> Self {
+        self.integral(round_div)
+    }
+
+    /// Converts an angle in units of `angle` to radians.
+    pub fn to_radians(&self, angle: AngleMode) -> Option<Self> {
+        let half_turn = match angle {
+            AngleMode::Radians => return Some(self.clone()),
+            AngleMode::Degrees => 180,
+            AngleMode::Gradians => 200,
+        };
+        let pi = Decimal::pi(self.precision + GUARD_DIGITS);
+        let radians = self
+            .mul(&pi)?
+            .div(&Decimal::from_i64(half_turn, self.precision))?;
+        Some(radians.with_precision(self.precision))
+    }
+
+    /// Converts an angle in radians to units of `angle`.
+    pub fn to_angle(&self, angle: AngleMode) -> Option<Self> {
+        let half_turn = match angle {
+            AngleMode::Radians => return Some(self.clone()),
+            AngleMode::Degrees => 180,
+            AngleMode::Gradians => 200,
+        };
+        let pi = Decimal::pi(self.precision + GUARD_DIGITS);
+        let turns = self
+            .mul(&Decimal::from_i64(half_turn, self.precision))?
+            .div(&pi)?;
+        Some(turns.with_precision(self.precision))
+    }
+
+    /// Raises this number to an integer power, by repeated squaring.
+    pub fn powi(&self, n: i64) -> Option<Self> {
+        if n < 0 {
+            return Decimal::from_i64(1, self.precision).div(&self.powi(n.checked_neg()?)?);
+        }
+        let wp = self.precision + GUARD_DIGITS + digit_count(&BigInt::from(n));
+        let mut base = self.with_precision(wp);
+        let mut acc = Decimal::from_i64(1, wp);
+        let mut n = n;
+        while n > 0 {
+            if n & 1 == 1 {
+                acc = acc.mul(&base)?;
+            }
+            n >>= 1;
+            // The last square is not needed, and may be out of range.
+            if n > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Some(acc.with_precision(self.precision))
+    }
+
+    /// Raises this number to any power, or returns `None` if the result is
+    /// not real.
+    pub fn pow(&self, exp: &Decimal) -> Option<Self> {
+        let precision = self.precision.max(exp.precision);
+        if let Some(n) = exp.to_i64() {
+            return self.with_precision(precision).powi(n);
+        }
+        if self.is_zero() {
+            return if exp.is_negative() {
+                None
+            } else {
+                Some(self.clone())
+            };
+        }
+        let wp = precision + GUARD_DIGITS;
+        let ln = self.with_precision(wp).ln()?;
+        Some(ln.mul(exp)?.exp()?.with_precision(precision))
+    }
+
+    /// Returns the square root, or `None` for negative numbers.
+    pub fn sqrt(&self) -> Option<Self> {
+        if self.is_negative() {
+            return None;
+        }
+        // Scale the mantissa up to twice the digits wanted, keeping the
+        // exponent even, then take its integer square root.
+        let wp = self.precision + 2;
+        let digits = digit_count(&self.mantissa);
+        let mut shift = (2 * wp).saturating_sub(digits) + 2;
+        if (self.exp - i64::from(shift)) % 2 != 0 {
+            shift += 1;
+        }
+        let mantissa = (&self.mantissa * pow10(shift)).sqrt();
+        let exp = (self.exp - i64::from(shift)) / 2;
+        Some(Decimal::new(mantissa, exp, self.precision))
+    }
+
+    /// Returns e raised to this number, or `None` if that is too large or
+    /// small to work out.
+    pub fn exp(&self) -> Option<Self> {
+        let x = self.to_f64();
+        if x.abs() > 1e15 {
+            return None;
+        }
+        // Work out exp(x / 2^k), where the series converges quickly, then
+        // square it k times. Each squaring doubles the relative error.
+        let halvings = if x.abs() > 0.5 {
+            (x.abs() / 0.5).log2().ceil() as u32
+        } else {
+            0
+        };
+        let wp = self.precision + GUARD_DIGITS + halvings * 3 / 10 + 1;
+        let scale = Decimal::from_integer(BigInt::from(2).pow(halvings), wp);
+        let r = self.with_precision(wp).div(&scale)?;
+
+        let mut sum = Decimal::from_i64(1, wp);
+        let mut term = Decimal::from_i64(1, wp);
+        for n in 1.. {
+            term = term.mul(&r)?.div(&Decimal::from_i64(n, wp))?;
+            if term.is_zero() || term.magnitude() < sum.magnitude() - i64::from(wp) {
+                break;
+            }
+            sum = sum.add(&term)?;
+        }
+        for _ in 0..halvings {
+            sum = sum.mul(&sum)?;
+        }
+        Some(sum.with_precision(self.precision))
+    }
+
+    /// Returns the natural logarithm, or `None` for numbers that are not
+    /// positive.
+    pub fn ln(&self) -> Option<Self> {
+        if self.is_negative() || self.is_zero() {
+            return None;
+        }
+        let wp = self.precision + GUARD_DIGITS;
+        let x = self.with_precision(wp);
+
+        // Start from a float estimate, made from the leading digits so that
+        // it works for numbers outside the range of a float, then refine it
+        // with Halley's method, which triples the correct digits each step.
+        let digits = self.mantissa.to_string();
+        let leading: f64 = format!("0.{}", &digits[..digits.len().min(17)])
+            .parse()
+            .unwrap();
+        let estimate = leading.ln() + (self.exp + digits.len() as i64) as f64 * LN_10;
+        let mut y = Decimal::from_f64(estimate, wp)?;
+        let two = Decimal::from_i64(2, wp);
+        for _ in 0..64 {
+            let exp_y = y.exp()?;
+            let delta = two.mul(&x.sub(&exp_y)?)?.div(&x.add(&exp_y)?)?;
+            y = y.add(&delta)?;
+            if delta.is_zero() || delta.magnitude() < y.magnitude() - i64::from(wp) + 2 {
+                break;
+            }
+        }
+        Some(y.with_precision(self.precision))
+    }
+
+    /// Returns the sine and cosine of an angle in radians, or `None` if the
+    /// angle is too large to reduce to within a turn.
+    pub fn sin_cos(&self) -> Option<(Self, Self)> {
+        // Taking out whole turns cancels as many digits as the angle has
+        // before the point, so they are worked to that many more digits.
+        let magnitude = self.magnitude().max(0);
+        if magnitude > i64::from(MAX_PRECISION) {
+            return None;
+        }
+        let wp = self.precision + GUARD_DIGITS + magnitude as u32;
+        let x = self.with_precision(wp);
+        let turn = Decimal::pi(wp).mul(&Decimal::from_i64(2, wp))?;
+        let r = x.sub(&x.div(&turn)?.round().mul(&turn)?)?;
+        let r2 = r.mul(&r)?.neg();
+
+        let (mut sin, mut sin_term) = (r.clone(), r);
+        let (mut cos, mut cos_term) = (Decimal::from_i64(1, wp), Decimal::from_i64(1, wp));
+        for n in 1.. {
+            sin_term = sin_term
+                .mul(&r2)?
+                .div(&Decimal::from_i64(2 * n * (2 * n + 1), wp))?;
+            cos_term = cos_term
+                .mul(&r2)?
+                .div(&Decimal::from_i64((2 * n - 1) * 2 * n, wp))?;
+            let small = |term: &Decimal| term.is_zero() || term.magnitude() < -i64::from(wp);
+            if small(&sin_term) && small(&cos_term) {
+                break;
+            }
+            sin = sin.add(&sin_term)?;
+            cos = cos.add(&cos_term)?;
+        }
+        Some((
+            sin.with_precision(self.precision),
+            cos.with_precision(self.precision),
+        ))
+    }
+
+    /// Returns the arctangent in radians.
+    pub fn atan(&self) -> Option<Self> {
+        let wp = self.precision + GUARD_DIGITS;
+        let one = Decimal::from_i64(1, wp);
+        let x = self.with_precision(wp);
+        if x.abs() > one {
+            // atan(x) = ±pi/2 - atan(1/x)
+            let half_pi = Decimal::pi(wp).div(&Decimal::from_i64(2, wp))?;
+            let half_pi = if x.is_negative() {
+                half_pi.neg()
+            } else {
+                half_pi
+            };
+            // A reciprocal too small to hold leaves pi/2 as it is.
+            let atan = match one.div(&x) {
+                Some(recip) => half_pi.sub(&recip.atan()?)?,
+                None => half_pi,
+            };
+            return Some(atan.with_precision(self.precision));
+        }
+
+        // Halve the angle until the series converges quickly, using
+        // atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))).
+        let mut x = x;
+        let mut halvings = 0;
+        while !x.is_zero() && x.magnitude() > -3 {
+            let hyp = one.add(&x.mul(&x)?)?.sqrt()?;
+            x = x.div(&one.add(&hyp)?)?;
+            halvings += 1;
+        }
+        let x2 = x.mul(&x)?.neg();
+        let (mut sum, mut power) = (x.clone(), x);
+        for n in 1.. {
+            power = power.mul(&x2)?;
+            let term = power.div(&Decimal::from_i64(2 * n + 1, wp))?;
+            if term.is_zero() || term.magnitude() < sum.magnitude() - i64::from(wp) {
+                break;
+            }
+            sum = sum.add(&term)?;
+        }
+        let scale = Decimal::from_integer(BigInt::from(2).pow(halvings), wp);
+        Some(sum.mul(&scale)?.with_precision(self.precision))
+    }
+
+    /// Returns the angle in radians from the positive x axis to the point
+    /// `(x, y)`, where `self` is `y`.
+    pub fn atan2(&self, x: &Decimal) -> Option<Self> {
+        let precision = self.precision.max(x.precision);
+        let pi = Decimal::pi(precision + GUARD_DIGITS);
+        let half_pi = pi.div(&Decimal::from_i64(2, precision))?;
+        let atan = match (x.mantissa.sign(), self.mantissa.sign()) {
+            (Sign::NoSign, Sign::NoSign) => Decimal::from_i64(0, precision),
+            (Sign::NoSign, Sign::Plus) => half_pi,
+            (Sign::NoSign, _) => half_pi.neg(),
+            (Sign::Plus, _) => self.div(x)?.atan()?,
+            (Sign::Minus, Sign::Minus) => self.div(x)?.atan()?.sub(&pi)?,
+            (Sign::Minus, _) => self.div(x)?.atan()?.add(&pi)?,
+        };
+        Some(atan.with_precision(precision))
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        let sign = self.mantissa.sign();
+        if sign != other.mantissa.sign() || sign == Sign::NoSign {
+            return sign.cmp(&other.mantissa.sign());
+        }
+        let ord = match self.magnitude().cmp(&other.magnitude()) {
+            // With the same leading digit position, the exponents differ by
+            // no more than the length of the mantissas.
+            Ordering::Equal => {
+                let exp = self.exp.min(other.exp);
+                let lhs = self.mantissa.abs() * pow10((self.exp - exp) as u32);
+                let rhs = other.mantissa.abs() * pow10((other.exp - exp) as u32);
+                lhs.cmp(&rhs)
+            }
+            ord => ord,
+        };
+        if sign == Sign::Minus {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.is_negative() { "-" } else { "" };
+        let digits = self.mantissa.abs().to_string();
+        let magnitude = self.magnitude();
+        if self.is_zero() {
+            write!(f, "0")
+        } else if magnitude >= i64::from(self.precision) || magnitude < -7 {
+            let (first, rest) = digits.split_at(1);
+            let point = if rest.is_empty() { "" } else { "." };
+            write!(f, "{}{}{}{}e{}", sign, first, point, rest, magnitude)
+        } else if self.exp >= 0 {
+            write!(f, "{}{}{}", sign, digits, "0".repeat(self.exp as usize))
+        } else if magnitude >= 0 {
+            let (int, frac) = digits.split_at(magnitude as usize + 1);
+            write!(f, "{}{}.{}", sign, int, frac)
+        } else {
+            let zeros = "0".repeat(-magnitude as usize - 1);
+            write!(f, "{}0.{}{}", sign, zeros, digits)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(x: f64) -> Decimal {
+        Decimal::from_f64(x, 30).unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!("0.3", dec(0.1).add(&dec(0.2)).unwrap().to_string());
+        assert_eq!(
+            "0.333333333333333333333333333333",
+            dec(1.0).div(&dec(3.0)).unwrap().to_string()
+        );
+        assert_eq!("1e40", dec(1e20).mul(&dec(1e20)).unwrap().to_string());
+        assert_eq!("-0.00125", dec(-1.25e-3).to_string());
+        assert_eq!("1e30", dec(1e30).add(&dec(1e-30)).unwrap().to_string());
+        assert!(dec(1.0).div(&dec(0.0)).is_none());
+        assert!(dec(-2.5) < dec(-2.25));
+        assert_eq!(dec(-3.0), dec(-2.5).floor());
+        assert_eq!(dec(-2.0), dec(-2.5).ceil());
+        assert_eq!(dec(-3.0), dec(-2.5).round());
+        assert_eq!(dec(1.0), dec(0.001).ceil());
+
+        // Results whose exponents are out of range are `None`, rather than
+        // overflowing the exponent.
+        let huge = dec(10.0).powi(1_000_000_000_000_000_000).unwrap();
+        let square = huge.mul(&huge).unwrap();
+        assert!(square.mul(&huge).is_none());
+        assert!(dec(10.0).powi(5_000_000_000_000_000_000).is_none());
+        assert!(dec(1.0).div(&square).unwrap().div(&huge).is_none());
+        assert!(dec(10.0).powi(i64::MIN).is_none());
+        assert_eq!(huge, huge.add(&dec(1.0)).unwrap());
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(
+            "3.1415926535897932384626433832795028841971693993751",
+            Decimal::pi(50).to_string()
+        );
+        assert_eq!(
+            "2.7182818284590452353602874713526624977572470937",
+            Decimal::e(47).to_string()
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let sqrt2 = dec(2.0).sqrt().unwrap();
+        assert_eq!("1.41421356237309504880168872421", sqrt2.to_string());
+        assert_eq!(dec(12.0), dec(144.0).sqrt().unwrap());
+        assert_eq!(
+            "2.30258509299404568401799145468",
+            dec(10.0).ln().unwrap().to_string()
+        );
+        assert_eq!("1024", dec(2.0).pow(&dec(10.0)).unwrap().to_string());
+        assert_eq!(
+            dec(3.0),
+            dec(27.0)
+                .pow(&dec(1.0).div(&dec(3.0)).unwrap())
+                .unwrap()
+                .round()
+        );
+        assert!(dec(-8.0).pow(&dec(0.5)).is_none());
+
+        let (sin, cos) = dec(1.0).sin_cos().unwrap();
+        assert_eq!("0.84147098480789650665250232163", sin.to_string());
+        assert_eq!("0.540302305868139717400936607443", cos.to_string());
+        let quarter_pi = Decimal::pi(30).div(&dec(4.0)).unwrap();
+        assert_eq!(quarter_pi, dec(1.0).atan().unwrap());
+        assert_eq!(quarter_pi.mul(&dec(-3.0)), dec(-1.0).atan2(&dec(-1.0)));
+    }
+}
@@ -2,50 +2,939 @@
 //!
 //! Handles the evaluation of expressions.
 
-use crate::ast::Expr;
+use crate::ast::{AngleMode, Expr, Func};
+use crate::calc::Environment;
+use crate::constants::{self, Definition};
+use crate::decimal::{Decimal, DEFAULT_PRECISION, GUARD_DIGITS};
+use crate::parser::Parser;
+use crate::units::{Quantity, Unit, UnitError};
+use crate::value::{decimals, exact_root, NumberMode, Value, MAX_EXACT_BITS};
 
-use std::collections::HashMap;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use serde_derive::{Deserialize, Serialize};
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::error;
+use std::f64::consts::{E, PI};
 use std::fmt;
 
-/// Given an `Expr`, recursively evaluate it and return the result.
-pub fn eval(ast: Expr, env: &mut HashMap<String, f64>) -> Result<f64, EvalError> {
-    use crate::ast::Expr::*;
+/// How deeply calls to user defined functions may nest before evaluation
+/// gives up, which stops runaway recursion.
+const MAX_CALL_DEPTH: usize = 128;
+
+/// The most decimal places `round` rounds to, either side of the point.
+/// This keeps the power of ten it scales by within `MAX_EXACT_BITS`, at up
+/// to 4 bits a digit.
+const MAX_ROUND_PLACES: u64 = MAX_EXACT_BITS / 4;
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+/// Options that change how expressions are evaluated.
+pub struct Settings {
+    /// The unit trig functions take, and inverse trig functions return, angles in.
+    pub angle: AngleMode,
+    pub number: NumberMode,
+    /// The number of significant digits decimal mode works to.
+    pub precision: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            angle: AngleMode::default(),
+            number: NumberMode::default(),
+            precision: DEFAULT_PRECISION,
+        }
+    }
+}
+
+/// The values of the built in constants looked up so far, along with the
+/// settings they were worked out in, as the values depend on the number
+/// mode and precision. They are worked out again once the settings change.
+struct ConstantValues {
+    settings: Settings,
+    values: HashMap<&'static str, Option<Value>>,
+}
+
+thread_local! {
+    static CONSTANT_VALUES: RefCell<ConstantValues> = RefCell::new(ConstantValues {
+        settings: Settings::default(),
+        values: HashMap::new(),
+    });
+}
+
+/// Returns the value of a built in constant, which depends on the number
+/// mode: pi, e and the constants worked out from them are worked out to the
+/// precision of decimal mode, and `i` is only defined in complex mode. Each
+/// constant is worked out once for the settings of the time.
+pub fn constant(name: &str, settings: Settings) -> Option<Value> {
+    let constant = constants::find(name)?;
+    let known = CONSTANT_VALUES.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.settings != settings {
+            cache.settings = settings;
+            cache.values.clear();
+        }
+        cache.values.get(constant.name).cloned()
+    });
+    if let Some(val) = known {
+        return val;
+    }
+    // Worked out without holding the cache, as a constant may be defined in
+    // terms of others, which are then looked up and stored in turn.
+    let val = constant_value(constant.definition, settings);
+    CONSTANT_VALUES.with(|cache| cache.borrow_mut().values.insert(constant.name, val.clone()));
+    val
+}
+
+fn constant_value(definition: Definition, settings: Settings) -> Option<Value> {
+    let decimal = settings.number == NumberMode::Decimal;
+    match definition {
+        Definition::Pi if decimal => Some(Value::Decimal(Decimal::pi(settings.precision))),
+        Definition::Pi => Some(Value::Real(PI)),
+        Definition::E if decimal => Some(Value::Decimal(Decimal::e(settings.precision))),
+        Definition::E => Some(Value::Real(E)),
+        Definition::I if settings.number == NumberMode::Complex => {
+            Some(Value::Complex(Complex64::i()))
+        }
+        Definition::I => None,
+        Definition::Calc(calc) => {
+            // Evaluated with no variables, so that only units and other
+            // constants are looked up.
+            let ast = Parser::new(calc)
+                .and_then(|mut parser| parser.parse())
+                .ok()?;
+            evaluate(ast, &mut Environment::new(), &[], settings).ok()
+        }
+    }
+}
 
+/// Returns an error if `name` is a built in constant, unless `force` is
+/// set, which lets variables and functions shadow constants.
+pub fn check_shadowing(name: &str, settings: Settings, force: bool) -> Result<(), EvalError> {
+    if !force && constant(name, settings).is_some() {
+        return Err(EvalError::Constant(name.to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// Anything a name can be bound to in an `Environment`.
+pub enum Binding {
+    Value(Value),
+    /// A user defined function's parameters and body.
+    Function(Vec<String>, Expr),
+}
+
+/// Returns the names of all user defined functions in an `Environment`.
+pub fn function_names(env: &Environment) -> HashSet<String> {
+    env.iter()
+        .filter(|(_, binding)| matches!(binding, Binding::Function(..)))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// The name of the last result, unless a variable is given the name.
+pub const ANS: &str = "ans";
+
+/// Evaluates a whole calculation, with `log` holding the results earlier
+/// ones can be referred back to by. Function definitions are added to the
+/// `Environment` and, having no value, return `None`. Built in constants
+/// cannot be assigned to or defined as functions unless `force` is set.
+pub fn run(
+    ast: Expr,
+    env: &mut Environment,
+    log: &[(String, Value)],
+    settings: Settings,
+    force: bool,
+) -> Result<Option<Value>, EvalError> {
     match ast {
-        Num(i) => Ok(i),
-        Ident(s) => match env.get(&s) {
-            Some(f) => Ok(*f),
-            None => Err(EvalError::UnknownVar(format!("Unknown variable: {}", s))),
+        Expr::Function(name, params, body) => {
+            check_shadowing(&name, settings, force)?;
+            // Calls in the body are looked up when the function is called,
+            // so they may be to functions defined later, but not to variables.
+            let mut names = vec![];
+            callees(&body, &mut names);
+            let value = names.into_iter().find(|callee| {
+                *callee != name && matches!(env.get(*callee), Some(Binding::Value(_)))
+            });
+            if let Some(callee) = value {
+                return Err(EvalError::NotAFunction(callee.to_string()));
+            }
+            env.insert(name, Binding::Function(params, *body));
+            Ok(None)
+        }
+        ast => Evaluator::new(env, log, settings, force)
+            .eval(ast)
+            .map(Some),
+    }
+}
+
+/// Adds the names of the user defined functions called in `expr` to `names`.
+fn callees<'e>(expr: &'e Expr, names: &mut Vec<&'e str>) {
+    match *expr {
+        Expr::Num(_) | Expr::Ident(_) | Expr::History(..) => {}
+        Expr::Add(ref lhs, ref rhs)
+        | Expr::Sub(ref lhs, ref rhs)
+        | Expr::Mul(ref lhs, ref rhs)
+        | Expr::Div(ref lhs, ref rhs)
+        | Expr::Pow(ref lhs, ref rhs)
+        | Expr::Mod(ref lhs, ref rhs)
+        | Expr::Convert(ref lhs, ref rhs) => {
+            callees(lhs, names);
+            callees(rhs, names);
+        }
+        Expr::Neg(ref expr)
+        | Expr::Fact(ref expr)
+        | Expr::Angle(ref expr, _)
+        | Expr::Assign(_, ref expr)
+        | Expr::Function(_, _, ref expr) => callees(expr, names),
+        Expr::Call(_, ref args) => args.iter().for_each(|arg| callees(arg, names)),
+        Expr::Apply(ref name, ref args) => {
+            names.push(name);
+            args.iter().for_each(|arg| callees(arg, names));
+        }
+    }
+}
+
+/// Evaluates an expression that must have a value, which function
+/// definitions do not.
+pub fn evaluate(
+    ast: Expr,
+    env: &mut Environment,
+    log: &[(String, Value)],
+    settings: Settings,
+) -> Result<Value, EvalError> {
+    Evaluator::new(env, log, settings, false).eval(ast)
+}
+
+/// Given an `Expr`, recursively evaluate it with the default `Settings`
+/// and no earlier results, and return the result.
+#[cfg(test)]
+fn eval(ast: Expr, env: &mut Environment) -> Result<Value, EvalError> {
+    Evaluator::new(env, &[], Settings::default(), false).eval(ast)
+}
+
+/// Evaluates expressions against an `Environment`, inside the body of
+/// a user defined function if `depth` is greater than zero.
+struct Evaluator<'a> {
+    env: &'a mut Environment,
+    /// The calculations so far, for `ans` and references like `$3`.
+    log: &'a [(String, Value)],
+    settings: Settings,
+    /// Whether variables may shadow built in constants.
+    force: bool,
+    /// Arguments of the user defined function being evaluated.
+    locals: HashMap<String, Value>,
+    depth: usize,
+    /// Whether names that are not defined may be read as units, which is
+    /// only set for the expression being evaluated. See `unit_operands`.
+    units: bool,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(
+        env: &'a mut Environment,
+        log: &'a [(String, Value)],
+        settings: Settings,
+        force: bool,
+    ) -> Self {
+        Evaluator {
+            env,
+            log,
+            settings,
+            force,
+            locals: HashMap::new(),
+            depth: 0,
+            units: false,
+        }
+    }
+
+    fn eval(&mut self, ast: Expr) -> Result<Value, EvalError> {
+        use crate::ast::Expr::*;
+
+        let units = std::mem::replace(&mut self.units, false);
+        match ast {
+            Num(i) => Ok(match self.settings.number {
+                NumberMode::Exact => Value::exact(i),
+                NumberMode::Decimal => Value::decimal(i, self.settings.precision),
+                _ => Value::Real(i),
+            }),
+            Ident(s) => self.lookup(s, units),
+            History(n, written) => self.recall(n, written),
+            Assign(s, e) => {
+                check_shadowing(&s, self.settings, self.force)?;
+                let val = self.eval(*e)?;
+                self.env.insert(s, Binding::Value(val.clone()));
+                Ok(val)
+            }
+            Add(e1, e2) => self.binary(*e1, *e2, |a, b| {
+                arithmetic(
+                    a,
+                    b,
+                    "+",
+                    |a, b| Ok(a + b),
+                    |x, y| x.combine(y, "+", |x, y| x + y),
+                )
+            }),
+            Sub(e1, e2) => self.binary(*e1, *e2, |a, b| {
+                arithmetic(
+                    a,
+                    b,
+                    "-",
+                    |a, b| Ok(a - b),
+                    |x, y| x.combine(y, "-", |x, y| x - y),
+                )
+            }),
+            Mul(e1, e2) => {
+                let units = self.unit_operands(&e1, &e2, units);
+                self.binary_with(*e1, *e2, units, |a, b| {
+                    arithmetic(a, b, "*", |a, b| Ok(a * b), |x, y| x.mul(y))
+                })
+            }
+            Div(e1, e2) => {
+                let units = self.unit_operands(&e1, &e2, units);
+                self.binary_with(*e1, *e2, units, |a, b| {
+                    arithmetic(a, b, "/", |a, b| Ok(a / b), |x, y| x.div(y))
+                })
+            }
+            Pow(e1, e2) => {
+                let number = self.settings.number;
+                self.binary_with(*e1, *e2, (units, false), |a, b| power(a, b, number))
+            }
+            Mod(e1, e2) => self.binary(*e1, *e2, |a, b| {
+                arithmetic(a, b, "%", modulo_value, |x, y| x.combine(y, "%", modulo))
+            }),
+            Neg(e) => Ok(-(self.eval(*e)?)),
+            Fact(e) => factorial_value(unitless(self.eval(*e)?, "!")?),
+            Angle(e, unit) => {
+                let val = unitless(self.eval(*e)?, &unit.to_string())?;
+                Ok(convert_angle(val, unit, self.settings.angle))
+            }
+            Convert(e1, e2) => self.binary_with(*e1, *e2, (false, true), convert),
+            Call(f, args) => self.call_builtin(f, args),
+            Apply(name, args) => self.call(name, args),
+            // Definitions are only valid as a whole calculation, see `run`.
+            Function(name, _, _) => Err(EvalError::NotAValue(name)),
+        }
+    }
+
+    /// Evaluates both sides of a binary operation before applying it.
+    fn binary<F>(&mut self, lhs: Expr, rhs: Expr, op: F) -> Result<Value, EvalError>
+    where
+        F: FnOnce(Value, Value) -> Result<Value, EvalError>,
+    {
+        self.binary_with(lhs, rhs, (false, false), op)
+    }
+
+    /// Like `binary`, but with whether each side may read names as units.
+    fn binary_with<F>(
+        &mut self,
+        lhs: Expr,
+        rhs: Expr,
+        units: (bool, bool),
+        op: F,
+    ) -> Result<Value, EvalError>
+    where
+        F: FnOnce(Value, Value) -> Result<Value, EvalError>,
+    {
+        self.units = units.0;
+        let lhs = self.eval(lhs)?;
+        self.units = units.1;
+        op(lhs, self.eval(rhs)?)
+    }
+
+    /// Returns whether each side of a product or quotient may read names as
+    /// units. Units are only read next to something they measure, as in
+    /// `3 m`, `x * m` or `(2 km)/h`, so that an undefined variable which
+    /// happens to be named like a unit is still an error, as in `t + 1`.
+    /// A product of units alone, as in `m/s`, is up to the expression it is
+    /// part of, which for `2 m/s` lets it be units.
+    fn unit_operands(&self, lhs: &Expr, rhs: &Expr, units: bool) -> (bool, bool) {
+        match (self.only_units(lhs), self.only_units(rhs)) {
+            (true, true) => (units, units),
+            sides => sides,
+        }
+    }
+
+    /// Returns whether an expression is made up of nothing but the names of
+    /// units, as in `kg*m/s^2`.
+    fn only_units(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Ident(name) => {
+                !self.locals.contains_key(name)
+                    && !self.env.contains_key(name)
+                    && name != ANS
+                    && constant(name, self.settings).is_none()
+                    && Unit::find(name).is_some()
+            }
+            Expr::Mul(lhs, rhs) | Expr::Div(lhs, rhs) => {
+                self.only_units(lhs) && self.only_units(rhs)
+            }
+            Expr::Pow(base, _) => self.only_units(base),
+            _ => false,
+        }
+    }
+
+    fn eval_args(&mut self, args: Vec<Expr>) -> Result<Vec<Value>, EvalError> {
+        let mut vals = Vec::with_capacity(args.len());
+        for arg in args {
+            vals.push(self.eval(arg)?);
+        }
+        Ok(vals)
+    }
+
+    /// Returns the value of a variable, looking in the arguments of the
+    /// current function, then the `Environment`, then at `ans`, then the
+    /// built in constants, and finally, if `units` is set, the units.
+    fn lookup(&self, name: String, units: bool) -> Result<Value, EvalError> {
+        if let Some(val) = self.locals.get(&name) {
+            return Ok(val.clone());
+        }
+        match self.env.get(&name) {
+            Some(Binding::Value(val)) => Ok(val.clone()),
+            Some(Binding::Function(..)) => Err(EvalError::NotAValue(name)),
+            None if name == ANS => self.recall(-1, name),
+            None => constant(&name, self.settings)
+                .or_else(|| {
+                    Unit::find(&name)
+                        .filter(|_| units)
+                        .map(|unit| Value::Quantity(Quantity::unit(unit)))
+                })
+                .ok_or(EvalError::UnknownVar(name)),
+        }
+    }
+
+    /// Returns the `n`th result in the log, counting from 1, or for a
+    /// negative `n`, the result `-n` back, so that -1 is the last result.
+    /// `written` is how the reference was written, for errors.
+    fn recall(&self, n: i64, written: String) -> Result<Value, EvalError> {
+        if n == 0 {
+            return Err(EvalError::ZeroResult(written));
+        }
+        let len = self.log.len() as i64;
+        let index = if n < 0 { len.saturating_add(n) } else { n - 1 };
+        if index < 0 || index >= len {
+            return Err(EvalError::NoResult(written, self.log.len()));
+        }
+        Ok(self.log[index as usize].1.clone())
+    }
+
+    /// Calls a built in function, checking it was given the right
+    /// number of arguments.
+    fn call_builtin(&mut self, f: Func, args: Vec<Expr>) -> Result<Value, EvalError> {
+        let (min, max) = f.arity();
+        if args.len() < min || max.is_some_and(|max| args.len() > max) {
+            return Err(EvalError::WrongArity(
+                f.name().to_string(),
+                min,
+                max,
+                args.len(),
+            ));
+        }
+
+        let vals = self.eval_args(args)?;
+        if vals.iter().any(Value::has_units) {
+            return apply_units(f, vals, self.settings.angle);
+        }
+        apply(f, &vals, self.settings)
+    }
+
+    /// Calls a user defined function, evaluating its body with its
+    /// parameters bound to the given arguments.
+    fn call(&mut self, name: String, args: Vec<Expr>) -> Result<Value, EvalError> {
+        let (params, body) = match self.env.get(&name) {
+            Some(Binding::Function(params, body)) => (params.clone(), body.clone()),
+            Some(Binding::Value(_)) => return Err(EvalError::NotAFunction(name)),
+            None => return Err(EvalError::UnknownVar(name)),
+        };
+        if args.len() != params.len() {
+            let arity = params.len();
+            return Err(EvalError::WrongArity(name, arity, Some(arity), args.len()));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(EvalError::RecursionLimit(name));
+        }
+
+        let vals = self.eval_args(args)?;
+        Evaluator {
+            env: &mut *self.env,
+            log: self.log,
+            settings: self.settings,
+            force: self.force,
+            locals: params.into_iter().zip(vals).collect(),
+            depth: self.depth + 1,
+            units: false,
+        }
+        .eval(body)
+    }
+}
+
+/// Applies an arithmetic operator `op`, with `units` if either value has
+/// units and with `numbers` otherwise.
+fn arithmetic(
+    a: Value,
+    b: Value,
+    op: &str,
+    numbers: fn(Value, Value) -> Result<Value, EvalError>,
+    units: fn(&Quantity, &Quantity) -> Result<Quantity, UnitError>,
+) -> Result<Value, EvalError> {
+    if !a.has_units() && !b.has_units() {
+        return numbers(a, b);
+    }
+    let (x, y) = (quantity(a, op)?, quantity(b, op)?);
+    Ok(Value::from(units(&x, &y)?))
+}
+
+/// Raises `a` to the power `b`, which must be a plain number.
+fn power(a: Value, b: Value, number: NumberMode) -> Result<Value, EvalError> {
+    match a {
+        Value::Quantity(q) => Ok(Value::from(q.powf(real(unitless(b, "^")?, "^")?)?)),
+        a => Ok(a.pow(unitless(b, "^")?, number)),
+    }
+}
+
+/// Converts a quantity to the units `target` is in.
+fn convert(val: Value, target: Value) -> Result<Value, EvalError> {
+    match target {
+        Value::Quantity(target) => Ok(Value::Quantity(quantity(val, "to")?.convert(&target)?)),
+        _ => Err(UnitError::NotAUnit.into()),
+    }
+}
+
+/// Returns the value as a quantity, which it is if it has units or is real.
+fn quantity(val: Value, op: &str) -> Result<Quantity, EvalError> {
+    match val {
+        Value::Quantity(q) => Ok(q),
+        val => real(val, op).map(Quantity::number),
+    }
+}
+
+/// Returns the value if it has no units, as `op` is only defined for
+/// plain numbers.
+fn unitless(val: Value, op: &str) -> Result<Value, EvalError> {
+    match val {
+        Value::Quantity(q) => Err(UnitError::NotANumber(op.to_string(), q.dimension()).into()),
+        val => Ok(val),
+    }
+}
+
+/// Applies a function to arguments, some of which have units. Rounding
+/// functions keep the units of their argument, while functions of several
+/// arguments need them all to measure the same thing.
+fn apply_units(f: Func, args: Vec<Value>, angle: AngleMode) -> Result<Value, EvalError> {
+    use crate::ast::Func::*;
+
+    let name = f.name();
+    let args = args
+        .into_iter()
+        .map(|arg| quantity(arg, name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let not_a_number = |q: &Quantity| UnitError::NotANumber(name.to_string(), q.dimension());
+    let x = &args[0];
+    match f {
+        Abs | Floor | Ceil | Round => match args.get(1) {
+            Some(digits) if !digits.is_number() => Err(not_a_number(digits).into()),
+            digits => {
+                let mut reals = vec![x.value()];
+                reals.extend(digits.and_then(Quantity::to_number));
+                Ok(Value::from(x.with_value(apply_real(f, &reals, angle))))
+            }
         },
-        Assign(s, e) => {
-            let val = eval(*e, env)?;
-            env.insert(s, val);
-            Ok(val)
-        }
-        Add(e1, e2) => Ok(eval(*e1, env)? + eval(*e2, env)?),
-        Sub(e1, e2) => Ok(eval(*e1, env)? - eval(*e2, env)?),
-        Mul(e1, e2) => Ok(eval(*e1, env)? * eval(*e2, env)?),
-        Div(e1, e2) => Ok(eval(*e1, env)? / eval(*e2, env)?),
-        Pow(e1, e2) => Ok((eval(*e1, env)?).powf(eval(*e2, env)?)),
-        Neg(e) => Ok(-(eval(*e, env)?)),
-        Abs(e) => Ok((eval(*e, env)?).abs()),
-        Floor(e) => Ok((eval(*e, env)?).floor()),
-        Log(e) => Ok((eval(*e, env)?).log10()),
-        Ln(e) => Ok((eval(*e, env)?).ln()),
-        Sin(e) => Ok((eval(*e, env)?).sin()),
-        Cos(e) => Ok((eval(*e, env)?).cos()),
-        Tan(e) => Ok((eval(*e, env)?).tan()),
-        Arcsin(e) => Ok((eval(*e, env)?).asin()),
-        Arccos(e) => Ok((eval(*e, env)?).acos()),
-        Arctan(e) => Ok((eval(*e, env)?).atan()),
+        Sqrt => Ok(Value::from(x.powf(0.5)?)),
+        Atan2 | Hypot | Max | Min => {
+            let mut reals = vec![x.value()];
+            for arg in args[1..].iter() {
+                reals.push(x.value_of(arg, name)?);
+            }
+            let val = apply_real(f, &reals, angle);
+            match f {
+                Atan2 => Ok(Value::Real(val)),
+                _ => Ok(Value::from(x.with_value(val))),
+            }
+        }
+        _ => {
+            let with_units = args.iter().find(|arg| !arg.is_number()).unwrap_or(x);
+            Err(not_a_number(with_units).into())
+        }
     }
 }
 
+/// Converts an angle in units of `unit` to units of `angle`.
+fn convert_angle(val: Value, unit: AngleMode, angle: AngleMode) -> Value {
+    match val {
+        _ if unit == angle => val,
+        Value::Complex(z) => Value::Complex(z * angle.from_radians(unit.to_radians(1.0))),
+        Value::Decimal(d) => match d.to_radians(unit).and_then(|r| r.to_angle(angle)) {
+            Some(converted) => Value::Decimal(converted),
+            None => Value::Real(angle.from_radians(unit.to_radians(d.to_f64()))),
+        },
+        real => {
+            let x = real.as_real().unwrap();
+            Value::Real(angle.from_radians(unit.to_radians(x)))
+        }
+    }
+}
+
+/// Returns the number if it is real, as `op` is only defined for real numbers.
+fn real(val: Value, op: &str) -> Result<f64, EvalError> {
+    val.as_real()
+        .ok_or_else(|| EvalError::NotReal(op.to_string()))
+}
+
+/// Returns `a mod b`, which is exact or a decimal if both are.
+fn modulo_value(a: Value, b: Value) -> Result<Value, EvalError> {
+    match (a, b) {
+        (Value::Rational(a), Value::Rational(b)) if !b.is_zero() => {
+            let floor = (&a / &b).floor();
+            Ok(Value::Rational(a - b * floor))
+        }
+        (a, b) => {
+            if let Some((x, y)) = decimals(&a, &b) {
+                let quotient = x.div(&y);
+                if let Some(rem) = quotient.and_then(|q| x.sub(&y.mul(&q.floor())?)) {
+                    return Ok(Value::Decimal(rem));
+                }
+            }
+            Ok(Value::Real(modulo(real(a, "mod")?, real(b, "mod")?)))
+        }
+    }
+}
+
+/// Returns `a mod b`, which, unlike `%` on floats, takes the sign of `b`.
+fn modulo(a: f64, b: f64) -> f64 {
+    let r = a % b;
+    if r != 0.0 && (r < 0.0) != (b < 0.0) {
+        r + b
+    } else {
+        r
+    }
+}
+
+/// Returns `n!`, which is exact or a decimal if `n` is and the result is
+/// not too large.
+fn factorial_value(n: Value) -> Result<Value, EvalError> {
+    match n {
+        Value::Rational(ref r) if r.is_integer() && !r.is_negative() => {
+            if let Some(exact) = r.to_integer().to_u64().and_then(exact_factorial) {
+                return Ok(Value::Rational(BigRational::from_integer(exact)));
+            }
+        }
+        Value::Decimal(ref d) => {
+            let exact = d.to_i64().filter(|n| *n >= 0);
+            if let Some(exact) = exact.and_then(|n| exact_factorial(n as u64)) {
+                return Ok(Value::Decimal(Decimal::from_integer(exact, d.precision())));
+            }
+        }
+        _ => {}
+    }
+    Ok(Value::Real(factorial(real(n, "!")?)))
+}
+
+fn exact_factorial(n: u64) -> Option<BigInt> {
+    let mut acc = BigInt::one();
+    for i in 2..=n {
+        acc *= i;
+        if acc.bits() > MAX_EXACT_BITS {
+            return None;
+        }
+    }
+    Some(acc)
+}
+
+/// Returns `n!`, which is only defined for non-negative integers.
+fn factorial(n: f64) -> f64 {
+    if n < 0.0 || n.fract() != 0.0 {
+        return f64::NAN;
+    }
+    // Anything past 170! overflows to infinity anyway.
+    (1..=n.min(171.0) as u64).fold(1.0, |acc, i| acc * i as f64)
+}
+
+/// Returns the sine and cosine of an angle in units of `angle`. Multiples of a
+/// quarter turn give exact results, so that `cos(90 deg)` is 0 rather than 6e-17.
+fn sin_cos(x: f64, angle: AngleMode) -> (f64, f64) {
+    let quarter = angle.turn() / 4.0;
+    if x % quarter == 0.0 {
+        match modulo(x / quarter, 4.0) as u8 {
+            0 => (0.0, 1.0),
+            1 => (1.0, 0.0),
+            2 => (0.0, -1.0),
+            _ => (-1.0, 0.0),
+        }
+    } else {
+        angle.to_radians(x).sin_cos()
+    }
+}
+
+/// Applies a function to its already evaluated arguments. Exact arguments
+/// give exact results where the function has a rational result for them,
+/// and decimal arguments give decimal results where it has a real one.
+/// Otherwise real arguments give real results unless, in complex mode, the
+/// function has no real result for them. The number of arguments must
+/// already have been checked against `Func::arity`.
+fn apply(f: Func, args: &[Value], settings: Settings) -> Result<Value, EvalError> {
+    if let (Func::Round, Some(places)) = (f, args.get(1)) {
+        if places
+            .as_real()
+            .is_some_and(|places| places.trunc().abs() > MAX_ROUND_PLACES as f64)
+        {
+            return Err(EvalError::TooManyPlaces(f.name().to_string()));
+        }
+    }
+    let exact: Option<Vec<&BigRational>> = args.iter().map(Value::as_rational).collect();
+    if let Some(exact) = exact.and_then(|args| apply_exact(f, &args)) {
+        return Ok(Value::Rational(exact));
+    }
+    let precision = args.iter().find_map(|arg| match arg {
+        Value::Decimal(d) => Some(d.precision()),
+        _ => None,
+    });
+    if let Some(precision) = precision {
+        let decimals: Option<Vec<Decimal>> =
+            args.iter().map(|arg| arg.to_decimal(precision)).collect();
+        if let Some(val) = decimals.and_then(|args| apply_decimal(f, &args, settings.angle)) {
+            return Ok(Value::Decimal(val));
+        }
+    }
+    let reals: Option<Vec<f64>> = args.iter().map(Value::as_real).collect();
+    if let Some(reals) = reals {
+        let val = apply_real(f, &reals, settings.angle);
+        if !val.is_nan()
+            || settings.number != NumberMode::Complex
+            || reals.iter().any(|x| x.is_nan())
+        {
+            return Ok(Value::Real(val));
+        }
+    }
+    let args: Vec<Complex64> = args.iter().map(Value::to_complex).collect();
+    apply_complex(f, &args, settings.angle).map(Value::complex)
+}
+
+/// Applies a function to exact arguments, if it has an exact result.
+fn apply_exact(f: Func, args: &[&BigRational]) -> Option<BigRational> {
+    use crate::ast::Func::*;
+
+    let x = args[0];
+    match f {
+        Abs => Some(x.abs()),
+        Floor => Some(x.floor()),
+        Ceil => Some(x.ceil()),
+        Round => match args.get(1) {
+            Some(digits) => {
+                let ten = BigRational::from_integer(BigInt::from(10));
+                let scale = ten.pow(digits.trunc().to_integer().to_i32()?);
+                Some((x * &scale).round() / scale)
+            }
+            None => Some(x.round()),
+        },
+        Sqrt => exact_root(x, 2),
+        Max => args.iter().max().map(|&max| max.clone()),
+        Min => args.iter().min().map(|&min| min.clone()),
+        _ => None,
+    }
+}
+
+/// Applies a function to decimal arguments, with angles in units of `angle`,
+/// if it has a real result. The function is worked out to extra digits, so
+/// that the rounding of its arguments and of angle conversions along the way
+/// does not show in the result.
+fn apply_decimal(f: Func, args: &[Decimal], angle: AngleMode) -> Option<Decimal> {
+    let precision = args[0].precision();
+    let args: Vec<Decimal> = args
+        .iter()
+        .map(|arg| arg.with_precision(precision + GUARD_DIGITS))
+        .collect();
+    apply_guarded(f, &args, angle).map(|val| val.with_precision(precision))
+}
+
+fn apply_guarded(f: Func, args: &[Decimal], angle: AngleMode) -> Option<Decimal> {
+    use crate::ast::Func::*;
+
+    let x = &args[0];
+    let one = Decimal::from_i64(1, x.precision());
+    match f {
+        Abs => Some(x.abs()),
+        Floor => Some(x.floor()),
+        Ceil => Some(x.ceil()),
+        Round => match args.get(1) {
+            Some(digits) => {
+                let scale = Decimal::from_i64(10, x.precision()).powi(digits.trunc().to_i64()?)?;
+                x.mul(&scale)?.round().div(&scale)
+            }
+            None => Some(x.round()),
+        },
+        Sqrt => x.sqrt(),
+        Log => match args.get(1) {
+            Some(base) => x.ln()?.div(&base.ln()?),
+            None => x.ln()?.div(&Decimal::from_i64(10, x.precision()).ln()?),
+        },
+        Ln => x.ln(),
+        Sin => Some(decimal_sin_cos(x, angle)?.0),
+        Cos => Some(decimal_sin_cos(x, angle)?.1),
+        Tan => {
+            let (sin, cos) = decimal_sin_cos(x, angle)?;
+            sin.div(&cos)
+        }
+        Arcsin | Arccos => {
+            if x.abs() > one {
+                return None;
+            }
+            let adjacent = one.sub(&x.mul(x)?)?.sqrt()?;
+            let radians = match f {
+                Arcsin => x.atan2(&adjacent)?,
+                _ => adjacent.atan2(x)?,
+            };
+            radians.to_angle(angle)
+        }
+        Arctan => x.atan()?.to_angle(angle),
+        Atan2 => x.atan2(&args[1])?.to_angle(angle),
+        Hypot => x.mul(x)?.add(&args[1].mul(&args[1])?)?.sqrt(),
+        Max => args.iter().max().cloned(),
+        Min => args.iter().min().cloned(),
+    }
+}
+
+/// Returns the sine and cosine of a decimal angle in units of `angle`,
+/// exactly at multiples of a quarter turn like `sin_cos`.
+fn decimal_sin_cos(x: &Decimal, angle: AngleMode) -> Option<(Decimal, Decimal)> {
+    let precision = x.precision();
+    let quarter = match angle {
+        AngleMode::Radians => None,
+        AngleMode::Degrees => Some(90),
+        AngleMode::Gradians => Some(100),
+    };
+    let quarters = match quarter {
+        _ if x.is_zero() => Some(0),
+        Some(quarter) => x.div(&Decimal::from_i64(quarter, precision))?.to_i64(),
+        None => None,
+    };
+    let (sin, cos) = match quarters.map(|n| n.rem_euclid(4)) {
+        Some(0) => (0, 1),
+        Some(1) => (1, 0),
+        Some(2) => (0, -1),
+        Some(_) => (-1, 0),
+        None => return x.to_radians(angle)?.sin_cos(),
+    };
+    Some((
+        Decimal::from_i64(sin, precision),
+        Decimal::from_i64(cos, precision),
+    ))
+}
+
+/// Applies a function to real arguments, with angles in units of `angle`.
+fn apply_real(f: Func, args: &[f64], angle: AngleMode) -> f64 {
+    use crate::ast::Func::*;
+
+    let x = args[0];
+    match f {
+        Abs => x.abs(),
+        Floor => x.floor(),
+        Ceil => x.ceil(),
+        Round => match args.get(1) {
+            Some(digits) => {
+                let scale = 10f64.powf(digits.trunc());
+                (x * scale).round() / scale
+            }
+            None => x.round(),
+        },
+        Sqrt => x.sqrt(),
+        Log => match args.get(1) {
+            Some(base) => x.log(*base),
+            None => x.log10(),
+        },
+        Ln => x.ln(),
+        Sin => sin_cos(x, angle).0,
+        Cos => sin_cos(x, angle).1,
+        Tan => {
+            let (sin, cos) = sin_cos(x, angle);
+            sin / cos
+        }
+        Arcsin => angle.from_radians(x.asin()),
+        Arccos => angle.from_radians(x.acos()),
+        Arctan => angle.from_radians(x.atan()),
+        Atan2 => angle.from_radians(x.atan2(args[1])),
+        Hypot => x.hypot(args[1]),
+        Max => args.iter().cloned().fold(x, f64::max),
+        Min => args.iter().cloned().fold(x, f64::min),
+    }
+}
+
+/// Applies a function to complex arguments, with angles in units of `angle`.
+/// Rounding functions round the real and imaginary parts separately.
+fn apply_complex(f: Func, args: &[Complex64], angle: AngleMode) -> Result<Complex64, EvalError> {
+    use crate::ast::Func::*;
+
+    let to_radians = angle.to_radians(1.0);
+    let from_radians = angle.from_radians(1.0);
+    let parts = |z: Complex64, f: fn(f64) -> f64| Complex64::new(f(z.re), f(z.im));
+    let z = args[0];
+    Ok(match f {
+        Abs => Complex64::new(z.norm(), 0.0),
+        Floor => parts(z, f64::floor),
+        Ceil => parts(z, f64::ceil),
+        Round => match args.get(1) {
+            Some(digits) => {
+                let scale = 10f64.powf(digits.re.trunc());
+                parts(z * scale, f64::round) / scale
+            }
+            None => parts(z, f64::round),
+        },
+        Sqrt => z.sqrt(),
+        Log => match args.get(1) {
+            Some(base) => z.ln() / base.ln(),
+            None => z.ln() / 10f64.ln(),
+        },
+        Ln => z.ln(),
+        Sin => (z * to_radians).sin(),
+        Cos => (z * to_radians).cos(),
+        Tan => (z * to_radians).tan(),
+        Arcsin => z.asin() * from_radians,
+        Arccos => z.acos() * from_radians,
+        Arctan => z.atan() * from_radians,
+        // The argument of x + iy, continued to complex x and y.
+        Atan2 => {
+            let (y, x) = (z, args[1]);
+            let r = (x * x + y * y).sqrt();
+            -Complex64::i() * ((x + Complex64::i() * y) / r).ln() * from_radians
+        }
+        Hypot => (z * z + args[1] * args[1]).sqrt(),
+        Max | Min => return Err(EvalError::NotReal(f.name().to_string())),
+    })
+}
+
 #[derive(Debug)]
 /// Defines the various errors that can occur during evaluation.
 pub enum EvalError {
+    /// Contains the name of the undefined variable.
     UnknownVar(String),
+    /// Contains the name of the function, the minimum and maximum number
+    /// of arguments it takes, and the number it was given.
+    WrongArity(String, usize, Option<usize>, usize),
+    /// Contains the name of a variable that was called like a function.
+    NotAFunction(String),
+    /// Contains the name of a function that was used like a variable.
+    NotAValue(String),
+    /// Contains the name of the function that recursed too deeply.
+    RecursionLimit(String),
+    /// Contains the name of an operation that was given a complex number
+    /// but is only defined for real numbers.
+    NotReal(String),
+    /// Contains a reference to an earlier result that there is none for,
+    /// as it was written, and the number of results there are.
+    NoResult(String, usize),
+    /// Contains a reference to result 0, as it was written, which there
+    /// never is as results are counted from 1.
+    ZeroResult(String),
+    /// Contains the name of a built in constant that was assigned to.
+    Constant(String),
+    /// Contains the name of a function that was asked to round to more
+    /// than `MAX_ROUND_PLACES` decimal places.
+    TooManyPlaces(String),
+    Units(UnitError),
 }
 
 impl fmt::Display for EvalError {
@@ -53,7 +942,41 @@ impl fmt::Display for EvalError {
         use self::EvalError::*;
 
         match *self {
-            UnknownVar(ref e) => write!(f, "{}", e),
+            UnknownVar(ref name) => write!(f, "Unknown variable: {}", name),
+            WrongArity(ref name, min, max, given) => {
+                let expected = match (min, max) {
+                    (min, Some(max)) if min == max => format!("{}", min),
+                    (min, Some(max)) => format!("{} to {}", min, max),
+                    (min, None) => format!("at least {}", min),
+                };
+                let plural = if max.unwrap_or(min) == 1 { "" } else { "s" };
+                write!(
+                    f,
+                    "{} takes {} argument{}, got {}",
+                    name, expected, plural, given
+                )
+            }
+            NotAFunction(ref name) => write!(f, "{} is not a function", name),
+            NotAValue(ref name) => write!(f, "{} is a function, not a value", name),
+            RecursionLimit(ref name) => write!(
+                f,
+                "Recursion limit reached: {} called itself more than {} times",
+                name, MAX_CALL_DEPTH
+            ),
+            NotReal(ref name) => write!(f, "{} is not defined for complex numbers", name),
+            NoResult(ref name, 0) => write!(f, "No result {}: there are no results yet", name),
+            NoResult(ref name, 1) => write!(f, "No result {}: there is only 1 result", name),
+            NoResult(ref name, len) => {
+                write!(f, "No result {}: there are only {} results", name, len)
+            }
+            ZeroResult(ref name) => write!(f, "No result {}: results are numbered from $1", name),
+            Constant(ref name) => write!(f, "{} is a built in constant", name),
+            TooManyPlaces(ref name) => write!(
+                f,
+                "{} can only round to {} decimal places",
+                name, MAX_ROUND_PLACES
+            ),
+            Units(ref e) => write!(f, "{}", e),
         }
     }
 }
@@ -63,11 +986,26 @@ impl error::Error for EvalError {
         use self::EvalError::*;
 
         match *self {
-            UnknownVar(ref e) => e,
+            UnknownVar(_) => "unknown variable",
+            WrongArity(..) => "wrong number of arguments",
+            NotAFunction(_) => "not a function",
+            NotAValue(_) => "not a value",
+            RecursionLimit(_) => "recursion limit reached",
+            NotReal(_) => "not defined for complex numbers",
+            NoResult(..) | ZeroResult(_) => "no such result",
+            Constant(_) => "assignment to a constant",
+            TooManyPlaces(_) => "too many decimal places",
+            Units(_) => "unit error",
         }
     }
 }
 
+impl From<UnitError> for EvalError {
+    fn from(err: UnitError) -> Self {
+        EvalError::Units(err)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,6 +1048,29 @@ mod tests {
         assert_eq!(11.5, evaled);
     }
 
+    #[test]
+    fn right_assoc_pow() {
+        let ast = Parser::new("2^3^2").unwrap().parse().unwrap();
+        let mut env = HashMap::new();
+        assert_eq!(512.0, eval(ast, &mut env).unwrap());
+
+        let ast = Parser::new("-2^2 + 2^-1").unwrap().parse().unwrap();
+        assert_eq!(-3.5, eval(ast, &mut env).unwrap());
+    }
+
+    #[test]
+    fn mod_and_factorial() {
+        let mut env = HashMap::new();
+        let mut calc = |s| eval(Parser::new(s).unwrap().parse().unwrap(), &mut env).unwrap();
+        assert_eq!(1.0, calc("7 % 3"));
+        assert_eq!(2.0, calc("-7 % 3"));
+        assert_eq!(-1.0, calc("5 % -3"));
+        assert_eq!(120.0, calc("5!"));
+        assert_eq!(1.0, calc("0!"));
+        assert_eq!(24.0, calc("2 * 3! * 2"));
+        assert!(calc("2.5!").is_nan());
+    }
+
     #[test]
     fn distribute_prop() {
         let ast = Parser::new("3(2+1)").unwrap().parse().unwrap();
@@ -127,4 +1088,245 @@ mod tests {
         let evaled = eval(ast, &mut env).unwrap();
         assert_eq!(21.0, evaled);
     }
+
+    #[test]
+    fn multi_arg_funcs() {
+        let mut env = HashMap::new();
+        let mut calc = |s| eval(Parser::new(s).unwrap().parse().unwrap(), &mut env).unwrap();
+        assert_eq!(7.0, calc("max(3, 7, 1 + 2)"));
+        assert_eq!(-2.0, calc("min(4, -2)"));
+        assert_eq!(5.0, calc("hypot(3, 4)"));
+        assert_eq!(3.0, calc("log(8, 2)"));
+        assert_eq!(2.0, calc("log(100)"));
+        assert_eq!(1.23, calc("round(1.23456, 2)"));
+        assert_eq!(std::f64::consts::FRAC_PI_2, calc("atan2(1, 0)"));
+    }
+
+    #[test]
+    fn wrong_arity() {
+        let ast = Parser::new("hypot(3)").unwrap().parse().unwrap();
+        let mut env = HashMap::new();
+        match eval(ast, &mut env) {
+            Err(e @ EvalError::WrongArity(..)) => {
+                assert_eq!("hypot takes 2 arguments, got 1", e.to_string())
+            }
+            other => panic!("expected WrongArity, got {:?}", other),
+        }
+
+        let ast = Parser::new("max()").unwrap().parse().unwrap();
+        match eval(ast, &mut env) {
+            Err(e @ EvalError::WrongArity(..)) => {
+                assert_eq!("max takes at least 1 argument, got 0", e.to_string())
+            }
+            other => panic!("expected WrongArity, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn user_functions() {
+        let mut env = HashMap::new();
+        let mut run_calc = |s: &str| {
+            let mut parser = Parser::new(s).unwrap();
+            parser.set_functions(function_names(&env));
+            run(
+                parser.parse().unwrap(),
+                &mut env,
+                &[],
+                Settings::default(),
+                false,
+            )
+        };
+        assert_eq!(None, run_calc("f(x) = x^2 + 3x").unwrap());
+        assert_eq!(Some(Value::Real(28.0)), run_calc("f(4)").unwrap());
+        assert_eq!(None, run_calc("g(x, y) = f(x) - y").unwrap());
+        assert_eq!(Some(Value::Real(8.0)), run_calc("g(2, 2)").unwrap());
+        // Parameters shadow variables, which are left untouched.
+        assert_eq!(Some(Value::Real(3.0)), run_calc("x = 3").unwrap());
+        assert_eq!(Some(Value::Real(4.0)), run_calc("f(1)").unwrap());
+        assert_eq!(Some(Value::Real(3.0)), run_calc("x").unwrap());
+
+        // Functions may call others defined after them, but not variables.
+        assert_eq!(None, run_calc("h(x) = k(x) + 1").unwrap());
+        assert!(run_calc("h(1)").is_err());
+        assert_eq!(None, run_calc("k(x) = 2x").unwrap());
+        assert_eq!(Some(Value::Real(7.0)), run_calc("h(3)").unwrap());
+        match run_calc("m(y) = x(y + 1)") {
+            Err(EvalError::NotAFunction(ref name)) => assert_eq!("x", name),
+            other => panic!("expected NotAFunction, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn user_function_errors() {
+        let mut env = HashMap::new();
+        let ast = Parser::new("f(n) = n * f(n - 1)").unwrap().parse().unwrap();
+        run(ast, &mut env, &[], Settings::default(), false).unwrap();
+
+        let mut parser = Parser::new("f(3)").unwrap();
+        parser.set_functions(function_names(&env));
+        match eval(parser.parse().unwrap(), &mut env) {
+            Err(EvalError::RecursionLimit(ref name)) => assert_eq!("f", name),
+            other => panic!("expected RecursionLimit, got {:?}", other),
+        }
+
+        let mut parser = Parser::new("f(1, 2)").unwrap();
+        parser.set_functions(function_names(&env));
+        match eval(parser.parse().unwrap(), &mut env) {
+            Err(e @ EvalError::WrongArity(..)) => {
+                assert_eq!("f takes 1 argument, got 2", e.to_string())
+            }
+            other => panic!("expected WrongArity, got {:?}", other),
+        }
+
+        let ast = Parser::new("f + 1").unwrap().parse().unwrap();
+        match eval(ast, &mut env) {
+            Err(EvalError::NotAValue(ref name)) => assert_eq!("f", name),
+            other => panic!("expected NotAValue, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn angle_modes() {
+        let mut env = HashMap::new();
+        env.insert(
+            "pi".to_string(),
+            Binding::Value(Value::Real(std::f64::consts::PI)),
+        );
+        let mut calc = |s, angle| {
+            let ast = Parser::new(s).unwrap().parse().unwrap();
+            let settings = Settings {
+                angle,
+                ..Settings::default()
+            };
+            let val = run(ast, &mut env, &[], settings, false).unwrap().unwrap();
+            val.as_real().unwrap()
+        };
+        assert_eq!(1.0, calc("sin(90)", AngleMode::Degrees));
+        assert_eq!(0.0, calc("cos(90)", AngleMode::Degrees));
+        assert_eq!(-1.0, calc("cos(200)", AngleMode::Gradians));
+        assert_eq!(0.0, calc("sin(pi)", AngleMode::Radians));
+        assert!((calc("sin(30)", AngleMode::Degrees) - 0.5).abs() < 1e-15);
+        assert_eq!(90.0, calc("arcsin(1)", AngleMode::Degrees));
+        assert_eq!(45.0, calc("atan2(1, 1)", AngleMode::Degrees));
+        assert_eq!(1.0, calc("sin(90 deg)", AngleMode::Radians));
+        assert_eq!(180.0, calc("pi rad", AngleMode::Degrees));
+        assert_eq!(200.0, calc("180deg", AngleMode::Gradians));
+    }
+
+    #[test]
+    fn complex_mode() {
+        let mut env = HashMap::new();
+        let mut calc = |s, number| {
+            let ast = Parser::new(s).unwrap().parse().unwrap();
+            let settings = Settings {
+                number,
+                ..Settings::default()
+            };
+            run(ast, &mut env, &[], settings, false).map(Option::unwrap)
+        };
+        assert!(calc("sqrt(-4)", NumberMode::Real).unwrap().is_nan());
+        assert!(calc("i", NumberMode::Real).is_err());
+
+        let complex = |re, im| Value::Complex(Complex64::new(re, im));
+        let mode = NumberMode::Complex;
+        assert_eq!(complex(0.0, 2.0), calc("sqrt(-4)", mode).unwrap());
+        assert_eq!(-1.0, calc("i^2", mode).unwrap());
+        assert_eq!(complex(3.0, 4.0), calc("(1 + 2i)^2 + 6", mode).unwrap());
+        assert_eq!(5.0, calc("abs(3 + 4i)", mode).unwrap());
+        assert_eq!(
+            complex(0.0, std::f64::consts::PI),
+            calc("ln(-1)", mode).unwrap()
+        );
+        assert_eq!(complex(1.0, -2.0), calc("floor(1.5 - 1.5i)", mode).unwrap());
+        assert_eq!(2.0, calc("sqrt(4)", mode).unwrap());
+        // Variables keep their values, which can be complex.
+        assert_eq!(complex(1.0, 1.0), calc("z = 1 + i", mode).unwrap());
+        assert_eq!(2.0, calc("z * (1 - i)", mode).unwrap());
+        match calc("max(i, 1)", mode) {
+            Err(EvalError::NotReal(ref name)) => assert_eq!("max", name),
+            other => panic!("expected NotReal, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn exact_mode() {
+        let mut env = HashMap::new();
+        let mut calc = |s| {
+            let ast = Parser::new(s).unwrap().parse().unwrap();
+            let settings = Settings {
+                number: NumberMode::Exact,
+                ..Settings::default()
+            };
+            run(ast, &mut env, &[], settings, false)
+                .unwrap()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!("1/2", calc("1/3 + 1/6"));
+        assert_eq!("0", calc("12*2/24 + 1 / 25 - 1.04"));
+        assert_eq!("1267650600228229401496703205376", calc("2^100"));
+        assert_eq!("2432902008176640000", calc("20!"));
+        assert_eq!("4/3", calc("sqrt(16/9)"));
+        assert_eq!("1/3", calc("-5/3 % 2"));
+        assert_eq!("3/4", calc("max(1/2, 3/4, 0.6)"));
+        assert_eq!("47/20", calc("round(2.345, 2)"));
+        assert_eq!(2f64.sqrt().to_string(), calc("sqrt(2)"));
+        // Inexact functions give floats, which stay floats.
+        assert_eq!("0.75", calc("1/2 + cos(0) / 4"));
+        assert_eq!("inf", calc("1/0"));
+    }
+
+    #[test]
+    fn units() {
+        let mut env = HashMap::new();
+        let mut calc = |s| {
+            let ast = Parser::new(s).unwrap().parse().unwrap();
+            run(ast, &mut env, &[], Settings::default(), false).map(|val| val.unwrap().to_string())
+        };
+        assert_eq!("3.2 km", calc("3 km + 200 m").unwrap());
+        assert_eq!("26.8224 m/s", calc("60 mph to m/s").unwrap());
+        assert_eq!("686.7 m·kg/s^2", calc("9.81 m/s^2 * 70 kg").unwrap());
+        assert_eq!("15", calc("3 km / 200 m").unwrap());
+        assert_eq!("4 m^2", calc("(2 m)^2").unwrap());
+        assert_eq!("3 m", calc("sqrt(9 m^2)").unwrap());
+        assert_eq!("2 km", calc("max(2 km, 300 m)").unwrap());
+        assert_eq!("-2 m", calc("-round(1.6 m)").unwrap());
+        assert_eq!("1.5 h", calc("90 minutes in hours").unwrap());
+        assert_eq!("1 N^65", calc("(1 N)^65").unwrap());
+
+        let mut err = |s| calc(s).unwrap_err().to_string();
+        assert_eq!("Cannot add mass to length", err("3 m + 2 kg"));
+        assert_eq!("Cannot subtract a number from length", err("3 km - 1"));
+        assert_eq!("Cannot convert time to length", err("2 s to m"));
+        assert_eq!("sin is not defined for length", err("sin(3 km)"));
+        assert_eq!(
+            "Cannot raise length to a fractional power",
+            err("sqrt(2 km)")
+        );
+        assert_eq!("^ is not defined for time", err("2^(3 s)"));
+        assert_eq!(
+            "Units cannot be raised past the power 127",
+            err("1 m^100 * m^100")
+        );
+        assert_eq!(
+            "Units cannot be raised past the power 127",
+            err("(1 N)^130")
+        );
+
+        // Names are only units next to something they measure, so an
+        // undefined variable is still an error.
+        assert_eq!("Unknown variable: t", err("t + 1"));
+        assert_eq!("Unknown variable: t", err("t"));
+        assert_eq!("Unknown variable: m", err("m/s"));
+        assert_eq!("Unknown variable: t", err("2 (t + 1)"));
+        assert_eq!("2 t", calc("2 t").unwrap());
+        assert_eq!("2 t", calc("t * 2").unwrap());
+        assert_eq!("0.5 s^-1", calc("1/(2 s)").unwrap());
+        assert_eq!("1000 kg", calc("2 t / 2 to kg").unwrap());
+        assert_eq!("7 kg·m/s^2", calc("kg*m/s^2 * 7").unwrap());
+
+        // Variables shadow units.
+        calc("m = 3").unwrap();
+        assert_eq!("6", calc("2 m").unwrap());
+    }
 }
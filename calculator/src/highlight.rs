@@ -0,0 +1,193 @@
+//! # Highlight
+//!
+//! Sorts the pieces of an input into styles, for syntax highlighting.
+
+use crate::ast::{Span, Token};
+use crate::calc::Environment;
+use crate::eval::{constant, Binding, Settings, ANS};
+use crate::lexer::Lexer;
+use crate::units::Unit;
+
+use std::collections::HashSet;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+/// The kinds of input that are highlighted differently.
+pub enum Style {
+    Number,
+    Operator,
+    Paren,
+    /// A closing paren with no opening paren before it to match.
+    UnmatchedParen,
+    /// A built in or user defined function.
+    Function,
+    /// A variable, constant or unit.
+    Name,
+    /// A name that is not defined, which is an error unless it is being
+    /// assigned to.
+    Unknown,
+    /// An angle unit, as in `90 deg`, or the `to` of a conversion.
+    Keyword,
+    /// Input that cannot be read at all, such as `$` or `1.2.3`.
+    Invalid,
+}
+
+/// Returns the style of every token in `calc`, in order. Names are looked
+/// up in `env`, apart from those being defined, like `x` in `x = 2` or `f`
+/// and `y` in `f(y) = 2y`.
+pub fn styles(calc: &str, env: &Environment, settings: Settings) -> Vec<(Span, Style)> {
+    let mut lexer = Lexer::new(calc);
+    let mut tokens = Vec::new();
+    loop {
+        match lexer.next_token() {
+            Ok((Token::Eof, _)) => break,
+            Ok((token, span)) => tokens.push((Some(token), span)),
+            // The offending input has been read past, so lexing carries on.
+            Err(e) => tokens.push((None, e.span())),
+        }
+    }
+
+    // Everything before an `=` is being defined.
+    let head = tokens
+        .iter()
+        .position(|(token, _)| *token == Some(Token::Equals))
+        .unwrap_or(0);
+    let defined: HashSet<&str> = tokens[..head]
+        .iter()
+        .filter_map(|(token, _)| match token {
+            Some(Token::Ident(name)) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let mut depth = 0;
+    let mut styles = Vec::with_capacity(tokens.len());
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        let called = matches!(tokens.get(i + 1), Some((Some(Token::LParen), _)));
+        let style = match token {
+            None => Style::Invalid,
+            Some(Token::Num(_)) | Some(Token::Superscript(_)) => Style::Number,
+            Some(Token::History(_)) => Style::Name,
+            Some(Token::LParen) => {
+                depth += 1;
+                Style::Paren
+            }
+            Some(Token::RParen) if depth == 0 => Style::UnmatchedParen,
+            Some(Token::RParen) => {
+                depth -= 1;
+                Style::Paren
+            }
+            Some(Token::Func(_)) => Style::Function,
+            Some(Token::Angle(_)) | Some(Token::Convert) => Style::Keyword,
+            Some(Token::Ident(name)) if i < head => {
+                if called {
+                    Style::Function
+                } else {
+                    Style::Name
+                }
+            }
+            Some(Token::Ident(name)) => match env.get(name) {
+                Some(Binding::Function(..)) => Style::Function,
+                Some(Binding::Value(_)) => Style::Name,
+                None if defined.contains(name.as_str()) => Style::Name,
+                None if name == ANS || constant(name, settings).is_some() => Style::Name,
+                None if Unit::find(name).is_some() => Style::Name,
+                None => Style::Unknown,
+            },
+            Some(_) => Style::Operator,
+        };
+        styles.push((*span, style));
+    }
+    styles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    fn style_names(calc: &str, env: &Environment) -> Vec<(&'static str, String)> {
+        styles(calc, env, Settings::default())
+            .into_iter()
+            .map(|(span, style)| {
+                let name = match style {
+                    Style::Number => "num",
+                    Style::Operator => "op",
+                    Style::Paren => "paren",
+                    Style::UnmatchedParen => "unmatched",
+                    Style::Function => "func",
+                    Style::Name => "name",
+                    Style::Unknown => "unknown",
+                    Style::Keyword => "keyword",
+                    Style::Invalid => "invalid",
+                };
+                (name, calc[span.start..span.end].to_string())
+            })
+            .collect()
+    }
+
+    fn pieces(pieces: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+        pieces
+            .iter()
+            .map(|&(style, text)| (style, text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn tokens() {
+        let mut env = Environment::new();
+        env.insert("x".to_string(), Binding::Value(Value::Real(2.0)));
+        assert_eq!(
+            pieces(&[
+                ("num", "2"),
+                ("func", "sin"),
+                ("paren", "("),
+                ("name", "x"),
+                ("op", "+"),
+                ("name", "pi"),
+                ("paren", ")"),
+                ("op", "*"),
+                ("unknown", "y"),
+                ("unmatched", ")"),
+            ]),
+            style_names("2sin(x + pi) * y)", &env)
+        );
+        assert_eq!(
+            pieces(&[
+                ("num", "60"),
+                ("name", "km"),
+                ("keyword", "to"),
+                ("name", "m"),
+                ("invalid", "$"),
+                ("invalid", "1.2.3"),
+            ]),
+            style_names("60 km to m $ 1.2.3", &env)
+        );
+    }
+
+    #[test]
+    fn definitions() {
+        let env = Environment::new();
+        assert_eq!(
+            pieces(&[
+                ("func", "f"),
+                ("paren", "("),
+                ("name", "y"),
+                ("paren", ")"),
+                ("op", "="),
+                ("num", "2"),
+                ("name", "y"),
+                ("op", "+"),
+                ("unknown", "z"),
+            ]),
+            style_names("f(y) = 2y + z", &env)
+        );
+        assert_eq!(
+            pieces(&[("name", "ans"), ("op", "+"), ("name", "$-2")]),
+            style_names("ans + $-2", &env)
+        );
+        assert_eq!(
+            pieces(&[("name", "z"), ("op", "="), ("num", "3")]),
+            style_names("z = 3", &env)
+        );
+    }
+}
@@ -4,88 +4,344 @@
 //!
 //! Handles the tokenization of raw &str input.
 
-use crate::ast::{get_function_token, Token};
+use crate::ast::{get_angle_token, get_function_token, get_keyword_token, Func, Span, Token};
 
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::CharIndices;
 
 use std::error;
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// Iterator that emits Tokens.
 pub struct Lexer<'a> {
-    source: Peekable<Chars<'a>>,
+    input: &'a str,
+    source: Peekable<CharIndices<'a>>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
         Lexer {
-            source: source.chars().peekable(),
+            input: source,
+            source: source.char_indices().peekable(),
         }
     }
 
     pub fn set_source(&mut self, source: &'a str) {
-        self.source = source.chars().peekable();
+        self.input = source;
+        self.source = source.char_indices().peekable();
     }
-}
 
-impl<'a> Iterator for Lexer<'a> {
-    type Item = Token;
+    /// Returns the byte offset of the next unread character.
+    fn position(&mut self) -> usize {
+        match self.source.peek() {
+            Some(&(i, _)) => i,
+            None => self.input.len(),
+        }
+    }
 
-    fn next(&mut self) -> Option<Token> {
-        let c = self.source.next();
-        match c {
-            Some('0'..='9') => {
-                // safe unwrap - we checked that c was Some() to get hree
-                let mut num = c.unwrap().to_string();
-                while let Some(n) = self.source.peek() {
-                    if n.is_numeric() || n == &'.' {
-                        // safe unwrap - we checked that n was Some() to get here
-                        num.push(self.source.next().unwrap());
-                    } else {
-                        break;
+    /// Returns the part of the source that `span` covers.
+    pub fn text(&self, span: Span) -> &'a str {
+        &self.input[span.start..span.end]
+    }
+
+    /// Returns the `n`th unread character without consuming anything.
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.source.clone().nth(n).map(|(_, c)| c)
+    }
+
+    /// Consumes the rest of a name onto the end of `name`, which goes on with
+    /// letters, digits and underscores, as in `x1`, `v_0` or `θ`. An
+    /// underscore must have more of the name after it.
+    fn name(&mut self, name: &mut String) {
+        let in_name = |c: char| is_letter(c) || c.is_ascii_digit();
+        while let Some(&(_, c)) = self.source.peek() {
+            let joined = c == '_' && self.peek_nth(1).is_some_and(in_name);
+            if !(in_name(c) || joined) {
+                break;
+            }
+            name.push(c);
+            self.source.next();
+        }
+    }
+
+    /// Consumes characters onto the end of `text` for as long as `accept` holds.
+    fn take_while<F: Fn(char) -> bool>(&mut self, text: &mut String, accept: F) {
+        while let Some(&(_, c)) = self.source.peek() {
+            if accept(c) {
+                text.push(c);
+                self.source.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Scans a number literal, the first character of which has already been
+    /// consumed. Handles decimals with an optional exponent (`6.022E23`),
+    /// hex, octal and binary integers (`0xFF`, `0o755`, `0b1011`) and `_`
+    /// between digits (`1_000_000`).
+    fn number(&mut self, start: usize, first: char) -> Result<Token, LexError> {
+        let mut text = first.to_string();
+        let radix = match (first, self.peek_nth(0)) {
+            ('0', Some('x')) | ('0', Some('X')) => 16,
+            ('0', Some('o')) | ('0', Some('O')) => 8,
+            ('0', Some('b')) | ('0', Some('B')) => 2,
+            _ => 10,
+        };
+
+        let value = if radix == 10 {
+            let is_digit = |c: char| c.is_ascii_digit() || c == '_';
+            self.take_while(&mut text, is_digit);
+            if first != '.' && self.peek_nth(0) == Some('.') {
+                self.take_while(&mut text, |c| c == '.');
+                self.take_while(&mut text, is_digit);
+            }
+
+            // Only take an exponent if digits follow, so that `2e` is still `2 * e`.
+            if let Some('e') | Some('E') = self.peek_nth(0) {
+                let digit = match self.peek_nth(1) {
+                    Some('+') | Some('-') => self.peek_nth(2),
+                    next => next,
+                };
+                if digit.is_some_and(|d| d.is_ascii_digit()) {
+                    // safe unwraps - we peeked these characters above
+                    text.push(self.source.next().unwrap().1);
+                    if let Some('+') | Some('-') = self.peek_nth(0) {
+                        text.push(self.source.next().unwrap().1);
                     }
+                    self.take_while(&mut text, is_digit);
                 }
+            }
 
-                Some(Token::Num(num.parse::<f64>().unwrap()))
+            // Anything number-like left over, such as the second `.` in
+            // `1.2.3`, makes the whole literal invalid.
+            let len = text.len();
+            self.take_while(&mut text, |c| c.is_ascii_digit() || c == '.' || c == '_');
+            if text.len() == len && valid_separators(&text, radix) {
+                text.replace('_', "").parse::<f64>().ok()
+            } else {
+                None
             }
-            Some('a'..='z') | Some('A'..='Z') => {
-                let mut ident = c.unwrap().to_string();
-                while let Some(ch) = self.source.peek() {
-                    if ch.is_alphabetic() {
-                        ident.push(self.source.next().unwrap());
+        } else {
+            // Take the prefix, then anything that could be a digit.
+            self.take_while(&mut text, |c| c.is_ascii_alphabetic());
+            self.take_while(&mut text, |c| c.is_ascii_hexdigit() || c == '_' || c == '.');
+            parse_radix(&text[2..], radix)
+        };
+
+        match value {
+            Some(value) => Ok(Token::Num(value)),
+            None => {
+                let span = Span::new(start, self.position());
+                Err(LexError::InvalidNumber(text, span))
+            }
+        }
+    }
+
+    /// Scans the number of a reference to an earlier result, after its `$`
+    /// or `_`, which may be negative for a result that many back, as in
+    /// `$-2`. Returns `None`, consuming nothing, if no number follows.
+    fn history(&mut self) -> Option<i64> {
+        let negative = self.peek_nth(0) == Some('-');
+        let first = if negative { 1 } else { 0 };
+        if !self.peek_nth(first).is_some_and(|d| d.is_ascii_digit()) {
+            return None;
+        }
+        if negative {
+            self.source.next();
+        }
+        let mut digits = String::new();
+        self.take_while(&mut digits, |c| c.is_ascii_digit());
+        // Numbers too large to fit refer to no result, like any other that
+        // is out of range.
+        let n = digits.parse::<i64>().unwrap_or(i64::MAX);
+        Some(if negative { -n } else { n })
+    }
+
+    /// Scans a power written in superscript, the first character of which
+    /// has already been consumed.
+    fn superscript(&mut self, start: usize, c: char) -> Result<Token, LexError> {
+        let mut text = c.to_string();
+        self.take_while(&mut text, |c| superscript_digit(c).is_some());
+        let power: String = text.chars().filter_map(superscript_digit).collect();
+        match power.parse::<f64>() {
+            Ok(n) => Ok(Token::Superscript(n)),
+            Err(_) => Err(LexError::InvalidNumber(
+                text,
+                Span::new(start, self.position()),
+            )),
+        }
+    }
+
+    /// Returns the next `Token` along with the `Span` it covers in the source.
+    /// Returns a `LexError` if a character that cannot start a token is found.
+    pub fn next_token(&mut self) -> Result<(Token, Span), LexError> {
+        while let Some(&(_, c)) = self.source.peek() {
+            if c == ' ' || c == '\n' || c == '\t' {
+                self.source.next();
+            } else {
+                break;
+            }
+        }
+
+        let (start, c) = match self.source.next() {
+            Some(next) => next,
+            None => {
+                let end = self.input.len();
+                return Ok((Token::Eof, Span::new(end, end)));
+            }
+        };
+
+        let token = match c {
+            '0'..='9' => self.number(start, c)?,
+            '.' if self.peek_nth(0).is_some_and(|d| d.is_ascii_digit()) => self.number(start, c)?,
+            // Symbols pasted from documents stand for what they look like.
+            '×' | '·' | '⋅' => Token::Mul,
+            '÷' => Token::Div,
+            '−' => Token::Sub,
+            '√' => Token::Func(Func::Sqrt),
+            'π' => Token::Ident("pi".to_string()),
+            c if superscript_digit(c).is_some() => self.superscript(start, c)?,
+            c if is_letter(c) => {
+                let mut word = c.to_string();
+                self.take_while(&mut word, is_letter);
+                let after_word = self.source.clone();
+                let mut ident = word.clone();
+                self.name(&mut ident);
+
+                // A function name followed by digits is applied to them, so
+                // that `sin2x` is still read as `sin(2x)`, unless they are
+                // part of its name, as in `atan2`.
+                let digits = ident[word.len()..]
+                    .find(|d: char| !d.is_ascii_digit())
+                    .unwrap_or(ident.len() - word.len());
+                if digits > 0
+                    && get_function_token(&ident).is_none()
+                    && get_function_token(&word).is_some()
+                {
+                    let with_digits = &ident[..word.len() + digits];
+                    let taken = if get_function_token(with_digits).is_some() {
+                        digits
                     } else {
-                        break;
+                        0
+                    };
+                    ident = ident[..word.len() + taken].to_string();
+                    self.source = after_word;
+                    for _ in 0..taken {
+                        self.source.next();
                     }
                 }
 
                 match get_function_token(ident.as_str()) {
-                    Some(Token::Func(f)) => Some(Token::Func(f)),
-                    _ => Some(Token::Ident(ident)),
+                    Some(Token::Func(f)) => Token::Func(f),
+                    _ => get_angle_token(ident.as_str())
+                        .or_else(|| get_keyword_token(ident.as_str()))
+                        .unwrap_or(Token::Ident(ident)),
+                }
+            }
+            '$' | '_' => match self.history() {
+                Some(n) => Token::History(n),
+                None => {
+                    let span = Span::new(start, start + c.len_utf8());
+                    return Err(LexError::InvalidChar(c, span));
                 }
+            },
+            '=' => Token::Equals,
+            '+' => Token::Add,
+            '-' => Token::Sub,
+            '*' => Token::Mul,
+            '/' => Token::Div,
+            '^' => Token::Pow,
+            '%' => Token::Mod,
+            '!' => Token::Fact,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            _ => {
+                let span = Span::new(start, start + c.len_utf8());
+                return Err(LexError::InvalidChar(c, span));
             }
-            Some('=') => Some(Token::Equals),
-            Some('+') => Some(Token::Add),
-            Some('-') => Some(Token::Sub),
-            Some('*') => Some(Token::Mul),
-            Some('/') => Some(Token::Div),
-            Some('^') => Some(Token::Pow),
-            Some('(') => Some(Token::LParen),
-            Some(')') => Some(Token::RParen),
-            None => Some(Token::Eof),
-            Some(' ') => self.next(),
-            Some('\n') => self.next(),
-            Some('\t') => self.next(),
-            Some(_) => None,
+        };
+
+        Ok((token, Span::new(start, self.position())))
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        match self.next_token() {
+            Ok((token, _)) => Some(token),
+            Err(_) => None,
         }
     }
 }
 
+/// Returns whether every `_` in a number literal sits between two digits.
+fn valid_separators(text: &str, radix: u32) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    chars.iter().enumerate().all(|(i, &c)| {
+        c != '_'
+            || (i > 0
+                && i + 1 < chars.len()
+                && chars[i - 1].is_digit(radix)
+                && chars[i + 1].is_digit(radix))
+    })
+}
+
+/// Parses the digits of a hex, octal or binary integer literal.
+/// Returns `None` if there are no digits or any are invalid.
+fn parse_radix(digits: &str, radix: u32) -> Option<f64> {
+    if digits.is_empty() || !valid_separators(digits, radix) {
+        return None;
+    }
+
+    let mut value = 0.0;
+    for c in digits.chars().filter(|&c| c != '_') {
+        value = value * radix as f64 + c.to_digit(radix)? as f64;
+    }
+    Some(value)
+}
+
+/// Returns whether `c` is a letter that names can be made of. `π` is not,
+/// as it is always pi.
+fn is_letter(c: char) -> bool {
+    c.is_alphabetic() && c != 'π'
+}
+
+/// Returns the digit or minus sign a superscript character stands for.
+fn superscript_digit(c: char) -> Option<char> {
+    match c {
+        '⁰' => Some('0'),
+        '¹' => Some('1'),
+        '²' => Some('2'),
+        '³' => Some('3'),
+        '⁴'..='⁹' => std::char::from_u32(c as u32 - '⁴' as u32 + '4' as u32),
+        '⁻' => Some('-'),
+        _ => None,
+    }
+}
+
 #[derive(Debug)]
-/// Defines the various errors that can occur during evaluation.
+/// Defines the various errors that can occur during lexing.
 pub enum LexError {
-    InvalidChar(String),
+    InvalidChar(char, Span),
+    /// Contains the text of the malformed number literal.
+    InvalidNumber(String, Span),
+}
+
+impl LexError {
+    /// Returns the `Span` of the offending input.
+    pub fn span(&self) -> Span {
+        use self::LexError::*;
+
+        match *self {
+            InvalidChar(_, span) => span,
+            InvalidNumber(_, span) => span,
+        }
+    }
 }
 
 impl fmt::Display for LexError {
@@ -93,7 +349,8 @@ impl fmt::Display for LexError {
         use self::LexError::*;
 
         match *self {
-            InvalidChar(ref e) => write!(f, "Lexing error: {}", e),
+            InvalidChar(c, _) => write!(f, "Invalid character: {}", c),
+            InvalidNumber(ref text, _) => write!(f, "Invalid number: {}", text),
         }
     }
 }
@@ -103,7 +360,8 @@ impl error::Error for LexError {
         use self::LexError::*;
 
         match *self {
-            InvalidChar(ref e) => e,
+            InvalidChar(..) => "invalid character",
+            InvalidNumber(..) => "invalid number",
         }
     }
 }
@@ -111,6 +369,7 @@ impl error::Error for LexError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::{AngleMode, Func};
 
     #[test]
     fn test_num_token() {
@@ -126,6 +385,66 @@ mod tests {
         assert_eq!(Token::Num(2903.0), lexer.next().unwrap());
     }
 
+    #[test]
+    fn test_number_literals() {
+        let mut lexer = Lexer::new("1e-9 6.022E23 .5 2. 0xFF 0b1011 0o755 1_000_000");
+        assert_eq!(Token::Num(1e-9), lexer.next().unwrap());
+        assert_eq!(Token::Num(6.022e23), lexer.next().unwrap());
+        assert_eq!(Token::Num(0.5), lexer.next().unwrap());
+        assert_eq!(Token::Num(2.0), lexer.next().unwrap());
+        assert_eq!(Token::Num(255.0), lexer.next().unwrap());
+        assert_eq!(Token::Num(11.0), lexer.next().unwrap());
+        assert_eq!(Token::Num(493.0), lexer.next().unwrap());
+        assert_eq!(Token::Num(1_000_000.0), lexer.next().unwrap());
+        assert_eq!(Token::Eof, lexer.next().unwrap());
+    }
+
+    #[test]
+    fn test_history_tokens() {
+        let mut lexer = Lexer::new("$3 _12 $-2 $1-2");
+        assert_eq!(Token::History(3), lexer.next().unwrap());
+        assert_eq!(Token::History(12), lexer.next().unwrap());
+        assert_eq!(Token::History(-2), lexer.next().unwrap());
+        assert_eq!(Token::History(1), lexer.next().unwrap());
+        assert_eq!(Token::Sub, lexer.next().unwrap());
+        assert_eq!(Token::Num(2.0), lexer.next().unwrap());
+        assert!(Lexer::new("$x").next_token().is_err());
+        assert!(Lexer::new("_").next_token().is_err());
+    }
+
+    #[test]
+    fn test_number_then_e() {
+        let mut lexer = Lexer::new("2e 3e+");
+        assert_eq!(Token::Num(2.0), lexer.next().unwrap());
+        assert_eq!(Token::Ident("e".to_string()), lexer.next().unwrap());
+        assert_eq!(Token::Num(3.0), lexer.next().unwrap());
+        assert_eq!(Token::Ident("e".to_string()), lexer.next().unwrap());
+        assert_eq!(Token::Add, lexer.next().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_numbers() {
+        for (source, text) in &[
+            ("1 + 1.2.3", "1.2.3"),
+            ("1 + 0b102", "0b102"),
+            ("1 + 0x", "0x"),
+            ("1 + 1__0", "1__0"),
+            ("1 + 1_", "1_"),
+            ("1 + 1e5.5", "1e5.5"),
+        ] {
+            let mut lexer = Lexer::new(source);
+            lexer.next_token().unwrap();
+            lexer.next_token().unwrap();
+            match lexer.next_token() {
+                Err(LexError::InvalidNumber(ref t, span)) => {
+                    assert_eq!(text, t);
+                    assert_eq!(Span::new(4, source.len()), span);
+                }
+                other => panic!("expected InvalidNumber for {}, got {:?}", source, other),
+            }
+        }
+    }
+
     #[test]
     fn test_add() {
         let mut lexer = Lexer::new("+");
@@ -150,6 +469,33 @@ mod tests {
         assert_eq!(Token::Div, lexer.next().unwrap());
     }
 
+    #[test]
+    fn test_mod_fact() {
+        let mut lexer = Lexer::new("% !");
+        assert_eq!(Token::Mod, lexer.next().unwrap());
+        assert_eq!(Token::Fact, lexer.next().unwrap());
+    }
+
+    #[test]
+    fn test_angle_units() {
+        let mut lexer = Lexer::new("90deg rad grad degree");
+        assert_eq!(Token::Num(90.0), lexer.next().unwrap());
+        assert_eq!(Token::Angle(AngleMode::Degrees), lexer.next().unwrap());
+        assert_eq!(Token::Angle(AngleMode::Radians), lexer.next().unwrap());
+        assert_eq!(Token::Angle(AngleMode::Gradians), lexer.next().unwrap());
+        assert_eq!(Token::Ident("degree".to_string()), lexer.next().unwrap());
+    }
+
+    #[test]
+    fn test_convert() {
+        let mut lexer = Lexer::new("km to m in tons");
+        assert_eq!(Token::Ident("km".to_string()), lexer.next().unwrap());
+        assert_eq!(Token::Convert, lexer.next().unwrap());
+        assert_eq!(Token::Ident("m".to_string()), lexer.next().unwrap());
+        assert_eq!(Token::Convert, lexer.next().unwrap());
+        assert_eq!(Token::Ident("tons".to_string()), lexer.next().unwrap());
+    }
+
     #[test]
     fn test_parens() {
         let mut lexer = Lexer::new("()");
@@ -157,9 +503,115 @@ mod tests {
         assert_eq!(Token::RParen, lexer.next().unwrap());
     }
 
+    #[test]
+    fn test_comma() {
+        let mut lexer = Lexer::new("max(1, 2)");
+        assert_eq!(Token::Func(Func::Max), lexer.next().unwrap());
+        assert_eq!(Token::LParen, lexer.next().unwrap());
+        assert_eq!(Token::Num(1.0), lexer.next().unwrap());
+        assert_eq!(Token::Comma, lexer.next().unwrap());
+        assert_eq!(Token::Num(2.0), lexer.next().unwrap());
+        assert_eq!(Token::RParen, lexer.next().unwrap());
+    }
+
+    #[test]
+    fn test_func_with_digits() {
+        let mut lexer = Lexer::new("atan2 x2");
+        assert_eq!(Token::Func(Func::Atan2), lexer.next().unwrap());
+        assert_eq!(Token::Ident("x2".to_string()), lexer.next().unwrap());
+    }
+
+    #[test]
+    fn test_func_then_digits() {
+        let mut lexer = Lexer::new("sin2x ln10 atan2");
+        assert_eq!(Token::Func(Func::Sin), lexer.next().unwrap());
+        assert_eq!(Token::Num(2.0), lexer.next().unwrap());
+        assert_eq!(Token::Ident("x".to_string()), lexer.next().unwrap());
+        assert_eq!(Token::Func(Func::Ln), lexer.next().unwrap());
+        assert_eq!(Token::Num(10.0), lexer.next().unwrap());
+        assert_eq!(Token::Func(Func::Atan2), lexer.next().unwrap());
+    }
+
     #[test]
     fn test_ident() {
         let mut lexer = Lexer::new("lol");
         assert_eq!(Token::Ident("lol".to_string()), lexer.next().unwrap());
     }
+
+    #[test]
+    fn test_ident_with_digits_and_underscores() {
+        let mut lexer = Lexer::new("x1 v_0 rate_2024 k_B θ sin_x 2x1 x_");
+        for name in &["x1", "v_0", "rate_2024", "k_B", "θ", "sin_x"] {
+            assert_eq!(Token::Ident(name.to_string()), lexer.next().unwrap());
+        }
+        assert_eq!(Token::Num(2.0), lexer.next().unwrap());
+        assert_eq!(Token::Ident("x1".to_string()), lexer.next().unwrap());
+        assert_eq!(Token::Ident("x".to_string()), lexer.next().unwrap());
+        assert!(lexer.next_token().is_err());
+        // An underscore and digits on their own are an earlier result.
+        let mut lexer = Lexer::new("x _2");
+        assert_eq!(Token::Ident("x".to_string()), lexer.next().unwrap());
+        assert_eq!(Token::History(2), lexer.next().unwrap());
+    }
+
+    #[test]
+    fn test_spans() {
+        let mut lexer = Lexer::new("12 + sin(x)");
+        assert_eq!(
+            (Token::Num(12.0), Span::new(0, 2)),
+            lexer.next_token().unwrap()
+        );
+        assert_eq!((Token::Add, Span::new(3, 4)), lexer.next_token().unwrap());
+        assert_eq!(Span::new(5, 8), lexer.next_token().unwrap().1);
+        assert_eq!(Span::new(8, 9), lexer.next_token().unwrap().1);
+        assert_eq!(Span::new(9, 10), lexer.next_token().unwrap().1);
+        assert_eq!(Span::new(10, 11), lexer.next_token().unwrap().1);
+        assert_eq!((Token::Eof, Span::new(11, 11)), lexer.next_token().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_char() {
+        let mut lexer = Lexer::new("2 # 3");
+        lexer.next_token().unwrap();
+        match lexer.next_token() {
+            Err(LexError::InvalidChar('#', span)) => assert_eq!(Span::new(2, 3), span),
+            other => panic!("expected InvalidChar, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unicode_symbols() {
+        let mut lexer = Lexer::new("2×3·4÷5−√πr x² x⁻¹ 2⁴⁵");
+        for token in vec![
+            Token::Num(2.0),
+            Token::Mul,
+            Token::Num(3.0),
+            Token::Mul,
+            Token::Num(4.0),
+            Token::Div,
+            Token::Num(5.0),
+            Token::Sub,
+            Token::Func(Func::Sqrt),
+            Token::Ident("pi".to_string()),
+            Token::Ident("r".to_string()),
+            Token::Ident("x".to_string()),
+            Token::Superscript(2.0),
+            Token::Ident("x".to_string()),
+            Token::Superscript(-1.0),
+            Token::Num(2.0),
+            Token::Superscript(45.0),
+        ] {
+            assert_eq!(token, lexer.next().unwrap());
+        }
+
+        let mut lexer = Lexer::new("x ≤ 2");
+        lexer.next_token().unwrap();
+        match lexer.next_token() {
+            Err(LexError::InvalidChar('≤', span)) => assert_eq!(Span::new(2, 5), span),
+            other => panic!("expected InvalidChar, got {:?}", other),
+        }
+        let mut lexer = Lexer::new("x⁻");
+        lexer.next_token().unwrap();
+        assert!(lexer.next_token().is_err());
+    }
 }
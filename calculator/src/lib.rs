@@ -1,8 +1,13 @@
 #![feature(box_syntax)]
-#![feature(try_trait)]
 
 mod ast;
 pub mod calc;
+mod constants;
+mod decimal;
 mod eval;
+mod highlight;
 mod lexer;
+mod notation;
 mod parser;
+mod units;
+mod value;
@@ -0,0 +1,341 @@
+//! # Notation
+//!
+//! Writes out numbers in fixed, scientific or engineering notation, or with
+//! SI prefixes, and groups their digits in thousands.
+
+use crate::decimal::MAX_PRECISION;
+
+use num_bigint::BigInt;
+use num_traits::{Signed, Zero};
+use serde_derive::{Deserialize, Serialize};
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize, Default)]
+/// How the digits of numbers are written out.
+pub enum Notation {
+    /// As many digits as it takes to tell the number apart, `0.1`, or a
+    /// set number of significant digits, `0.100`.
+    #[default]
+    Shortest,
+    /// A set number of decimal places, `3.14`.
+    Fixed,
+    /// One digit before the point and a power of ten, `3.14e2`.
+    Scientific,
+    /// Like scientific, but with a power of ten that is a multiple of
+    /// three, `314e0`.
+    Engineering,
+    /// Like engineering, but with an SI prefix for the power of ten,
+    /// `12.5k`. Powers of ten with no prefix are written out as they are.
+    Si,
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Notation::Shortest => write!(f, "shortest"),
+            Notation::Fixed => write!(f, "fixed"),
+            Notation::Scientific => write!(f, "scientific"),
+            Notation::Engineering => write!(f, "engineering"),
+            Notation::Si => write!(f, "si"),
+        }
+    }
+}
+
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shortest" => Ok(Notation::Shortest),
+            "fixed" => Ok(Notation::Fixed),
+            "sci" | "scientific" => Ok(Notation::Scientific),
+            "eng" | "engineering" => Ok(Notation::Engineering),
+            "si" => Ok(Notation::Si),
+            _ => Err(format!("Unknown notation: {}", s)),
+        }
+    }
+}
+
+/// The number of decimal places fixed notation uses when no number of
+/// digits is set.
+pub const DEFAULT_PLACES: u32 = 6;
+
+/// The most digits a number is written out with before the point. Past
+/// this, fixed and shortest notation switch to scientific notation, as
+/// decimal mode does for numbers past its precision. Every float fits.
+const MAX_INT_DIGITS: i64 = MAX_PRECISION as i64;
+
+/// The SI prefixes for powers of ten, the same as those units take, less
+/// those for powers that are not a multiple of three.
+const PREFIXES: &[(i64, &str)] = &[
+    (12, "T"),
+    (9, "G"),
+    (6, "M"),
+    (3, "k"),
+    (0, ""),
+    (-3, "m"),
+    (-6, "u"),
+    (-9, "n"),
+    (-12, "p"),
+    (-15, "f"),
+];
+
+/// The digits of a number, `0.d1 d2 d3... * 10^point`, with no leading
+/// or trailing zeros. Zero has no digits.
+struct Digits {
+    negative: bool,
+    digits: Vec<u8>,
+    point: i64,
+}
+
+impl Digits {
+    fn new(mantissa: &BigInt, exp: i64) -> Self {
+        let mut digits: Vec<u8> = mantissa
+            .abs()
+            .to_string()
+            .bytes()
+            .map(|b| b - b'0')
+            .collect();
+        let point = exp + digits.len() as i64;
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Digits {
+            negative: mantissa.is_negative(),
+            digits,
+            point,
+        }
+    }
+
+    /// Returns the `i`th digit, which is zero past either end.
+    fn digit(&self, i: i64) -> char {
+        let d = if i < 0 {
+            0
+        } else {
+            *self.digits.get(i as usize).unwrap_or(&0)
+        };
+        (b'0' + d) as char
+    }
+
+    fn digits(&self, range: std::ops::Range<i64>) -> String {
+        range.map(|i| self.digit(i)).collect()
+    }
+
+    /// Rounds to the first `keep` digits, with halves rounded away from zero.
+    fn round(&mut self, keep: i64) {
+        if keep < 0 {
+            self.digits.clear();
+        } else if (keep as usize) < self.digits.len() {
+            let keep = keep as usize;
+            let up = self.digits[keep] >= 5;
+            self.digits.truncate(keep);
+            if up {
+                // Carry the one, which can make `999` into `1000`.
+                match self.digits.iter().rposition(|&d| d != 9) {
+                    Some(i) => {
+                        self.digits.truncate(i + 1);
+                        self.digits[i] += 1;
+                    }
+                    None => {
+                        self.digits = vec![1];
+                        self.point += 1;
+                    }
+                }
+            }
+            while self.digits.last() == Some(&0) {
+                self.digits.pop();
+            }
+        }
+    }
+
+    fn sign(&self) -> &'static str {
+        if self.negative && !self.digits.is_empty() {
+            "-"
+        } else {
+            ""
+        }
+    }
+
+    /// Writes out the digits from `0` to `int` before the point and those
+    /// from `int` to `end` after it, then the power of ten `exp`, if any.
+    fn write(&self, int: i64, end: i64, exp: Option<i64>) -> String {
+        let mut out = self.sign().to_string();
+        if int <= 0 {
+            out.push('0');
+        } else {
+            out.push_str(&self.digits(0..int));
+        }
+        if end > int {
+            out.push('.');
+            out.push_str(&self.digits(int..end));
+        }
+        if let Some(exp) = exp {
+            out.push_str(&format!("e{}", exp));
+        }
+        out
+    }
+}
+
+/// Writes out `mantissa * 10^exp`, as both floats and decimals can be
+/// split into, in `notation`. `digits` is the number of decimal places in
+/// fixed notation, and otherwise the number of significant digits, which
+/// are all written out if it is `None`. Returns `None` for the shortest
+/// notation with every digit, which is left to each kind of number.
+pub fn write_number(
+    mantissa: &BigInt,
+    exp: i64,
+    notation: Notation,
+    digits: Option<u32>,
+) -> Option<String> {
+    let mut number = Digits::new(mantissa, exp);
+    let notation = match notation {
+        Notation::Shortest | Notation::Fixed if number.point > MAX_INT_DIGITS => {
+            Notation::Scientific
+        }
+        notation => notation,
+    };
+    if let (Some(sig), false) = (digits, notation == Notation::Fixed) {
+        number.round(i64::from(sig.max(1)));
+    }
+    // The power of ten of the first digit.
+    let magnitude = if mantissa.is_zero() {
+        0
+    } else {
+        number.point - 1
+    };
+    let len = number.digits.len() as i64;
+    let sig = digits.map_or(len, |sig| i64::from(sig.max(1)));
+    match notation {
+        Notation::Shortest => {
+            digits.map(|_| number.write(number.point, sig.max(number.point), None))
+        }
+        Notation::Fixed => {
+            let places = i64::from(digits.unwrap_or(DEFAULT_PLACES));
+            number.round(number.point + places);
+            Some(number.write(number.point, number.point + places, None))
+        }
+        Notation::Scientific => Some(number.write(1, sig.max(1), Some(magnitude))),
+        Notation::Engineering | Notation::Si => {
+            let exp = magnitude.div_euclid(3) * 3;
+            let int = magnitude - exp + 1;
+            let prefix = PREFIXES
+                .iter()
+                .find(|&&(power, _)| power == exp)
+                .filter(|_| notation == Notation::Si);
+            Some(match prefix {
+                Some((_, prefix)) => number.write(int, sig.max(int), None) + prefix,
+                None => number.write(int, sig.max(int), Some(exp)),
+            })
+        }
+    }
+}
+
+/// Puts commas between the thousands of every number in `written`, as in
+/// `1,234,567.5`. Digits after a point, or in a power of ten or of a unit,
+/// are left as they are.
+pub fn group_thousands(written: &str) -> String {
+    let mut grouped = String::with_capacity(written.len() * 4 / 3);
+    let mut rest = written;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        let len = rest[start..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len() - start);
+        let (before, digits) = (&rest[..start], &rest[start..start + len]);
+        grouped.push_str(before);
+        if before.trim_end_matches('-').ends_with(&['.', 'e', '^'][..]) {
+            grouped.push_str(digits);
+        } else {
+            for (i, digit) in digits.chars().enumerate() {
+                if i > 0 && (len - i) % 3 == 0 {
+                    grouped.push(',');
+                }
+                grouped.push(digit);
+            }
+        }
+        rest = &rest[start + len..];
+    }
+    grouped.push_str(rest);
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(x: &str, notation: Notation, digits: Option<u32>) -> String {
+        let (mantissa, exp) = match x.find('e') {
+            Some(e) => (&x[..e], x[e + 1..].parse().unwrap()),
+            None => (x, 0),
+        };
+        write_number(&mantissa.parse().unwrap(), exp, notation, digits).unwrap()
+    }
+
+    #[test]
+    fn fixed() {
+        assert_eq!("3.14", write("314159e-5", Notation::Fixed, Some(2)));
+        assert_eq!("100.00", write("99999e-3", Notation::Fixed, Some(2)));
+        assert_eq!("0.01", write("6e-3", Notation::Fixed, Some(2)));
+        assert_eq!("0.00", write("-4e-3", Notation::Fixed, Some(2)));
+        assert_eq!("-2", write("-15e-1", Notation::Fixed, Some(0)));
+        assert_eq!("120.000000", write("12e1", Notation::Fixed, None));
+        assert_eq!("0", write("0", Notation::Fixed, Some(0)));
+        // Numbers too large to write out in full are written in scientific
+        // notation.
+        assert_eq!("1.00e2000", write("1e2000", Notation::Fixed, Some(3)));
+        assert_eq!(
+            "1.5e1000000000000000",
+            write("15e999999999999999", Notation::Fixed, None)
+        );
+        assert_eq!("2e1000", write("2e1000", Notation::Shortest, Some(1)));
+    }
+
+    #[test]
+    fn scientific() {
+        assert_eq!("3.14159e0", write("314159e-5", Notation::Scientific, None));
+        assert_eq!("3.14e-3", write("314159e-8", Notation::Scientific, Some(3)));
+        assert_eq!("1.0e3", write("9999e-1", Notation::Scientific, Some(2)));
+        assert_eq!("-1.200e9", write("-12e8", Notation::Scientific, Some(4)));
+        assert_eq!("0e0", write("0", Notation::Scientific, None));
+    }
+
+    #[test]
+    fn engineering() {
+        assert_eq!("314.159e0", write("314159e-3", Notation::Engineering, None));
+        assert_eq!(
+            "3.1416e3",
+            write("314159e-2", Notation::Engineering, Some(5))
+        );
+        assert_eq!("120e3", write("123456", Notation::Engineering, Some(2)));
+        assert_eq!("-12e-6", write("-12e-6", Notation::Engineering, None));
+        assert_eq!("1.00e3", write("9999e-1", Notation::Engineering, Some(3)));
+    }
+
+    #[test]
+    fn significant_digits() {
+        assert_eq!("0.333", write("3333e-4", Notation::Shortest, Some(3)));
+        assert_eq!("2.50", write("25e-1", Notation::Shortest, Some(3)));
+        assert_eq!("120000", write("123456", Notation::Shortest, Some(2)));
+        assert_eq!("0.0012", write("1234e-6", Notation::Shortest, Some(2)));
+    }
+
+    #[test]
+    fn si_prefixes() {
+        assert_eq!("12.3k", write("12345", Notation::Si, Some(3)));
+        assert_eq!("-4.7u", write("-47e-7", Notation::Si, None));
+        assert_eq!("250", write("250", Notation::Si, None));
+        assert_eq!("1e15", write("1e15", Notation::Si, None));
+        assert_eq!("0", write("0", Notation::Si, None));
+    }
+
+    #[test]
+    fn thousands() {
+        assert_eq!("1,234,567.8912", group_thousands("1234567.8912"));
+        assert_eq!("-123,456e-1000", group_thousands("-123456e-1000"));
+        assert_eq!("1,000 - 25,000i", group_thousands("1000 - 25000i"));
+        assert_eq!("10,000/3", group_thousands("10000/3"));
+        assert_eq!("1,500 m^1000", group_thousands("1500 m^1000"));
+        assert_eq!("inf", group_thousands("inf"));
+    }
+}
@@ -3,23 +3,31 @@
 //! Handles the parsing of a mathematical expression to construct
 //! an AST that can be evaluated.
 
+use std::collections::HashSet;
 use std::error;
 use std::fmt;
-use std::option::NoneError;
 
-use crate::ast::{Expr, Func, Precedence, Token};
-use crate::lexer::Lexer;
+use crate::ast::{Assoc, Expr, Fixity, Operator, Precedence, Span, Token};
+use crate::constants;
+use crate::eval::ANS;
+use crate::lexer::{LexError, Lexer};
 
 #[derive(Debug)]
 /// Object that takes a `&str` and returns a AST of `Expr`.
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_token: Token,
+    current_span: Span,
     // this parser actually works as an LL(0), so peek_token
     // technically isn't even necessary, but I'm keeping it
     // in case it is needed for future additions.
     peek_token: Token,
-    // Constants and then variable assignment
+    peek_span: Span,
+    // Names of user defined functions, so that `f(x)` can be
+    // told apart from implicit multiplication like `a(x)`.
+    functions: HashSet<String>,
+    // Parameters of the function whose body is being parsed, if any.
+    params: Option<Vec<String>>,
 }
 
 impl<'a> Parser<'a> {
@@ -27,82 +35,111 @@ impl<'a> Parser<'a> {
     /// it will return a `ParseError`.
     pub fn new(source: &'a str) -> Result<Self, ParseError> {
         let mut lexer = Lexer::new(source);
-        let cur = lexer.next()?;
-        let peek = lexer.next()?;
+        let (cur, cur_span) = lexer.next_token()?;
+        let (peek, peek_span) = lexer.next_token()?;
 
         Ok(Parser {
-            lexer: lexer,
+            lexer,
             current_token: cur,
+            current_span: cur_span,
             peek_token: peek,
+            peek_span,
+            functions: HashSet::new(),
+            params: None,
         })
     }
 
+    /// Sets the names that should be parsed as calls to user defined functions.
+    pub fn set_functions(&mut self, functions: HashSet<String>) {
+        self.functions = functions;
+    }
+
+    /// Returns whether `name` followed by a paren calls a user defined
+    /// function rather than multiplying. In a function body, any name that
+    /// is not a parameter or a constant is called, so that a function may
+    /// call another that is only defined later.
+    fn is_call(&self, name: &str) -> bool {
+        if self.functions.contains(name) {
+            return true;
+        }
+        match self.params {
+            Some(ref params) => {
+                !params.iter().any(|param| param == name)
+                    && constants::find(name).is_none()
+                    && name != ANS
+            }
+            None => false,
+        }
+    }
+
     /// Steps into the next token.
     fn next_token(&mut self) -> Result<(), ParseError> {
         self.current_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next()?;
+        self.current_span = self.peek_span;
+        let (peek, peek_span) = self.lexer.next_token()?;
+        self.peek_token = peek;
+        self.peek_span = peek_span;
         Ok(())
     }
 
     /// Returns a single Expr that represents the AST of the entire computation.
     /// If there was an error during parsing, returns a `ParseError`.
     pub fn parse(&mut self) -> Result<Expr, ParseError> {
-        let expr = self.parse_expr(Precedence::Lowest);
-        match expr {
-            Ok(e) => Ok(e),
-            Err(e) => Err(e),
+        let expr = if self.at_definition() {
+            self.parse_definition()?
+        } else {
+            self.parse_expr(Precedence::LOWEST)?
+        };
+        // Anything left over means the input was not a single expression.
+        if self.current_token != Token::Eof {
+            return Err(self.invalid_input());
         }
+        Ok(expr)
     }
 
     fn parse_expr(&mut self, prec: Precedence) -> Result<Expr, ParseError> {
         let mut left = self.parse_atom()?;
-        while prec < self.current_token.get_precedence() {
-            if self.current_token == Token::Eof {
-                break;
+        loop {
+            // A power in superscript binds like a postfix operator, so that
+            // `-x²` is `-(x^2)` and `2^3²` is `2^9`.
+            if let Token::Superscript(n) = self.current_token {
+                if Precedence::POSTFIX <= prec {
+                    break;
+                }
+                self.next_token()?;
+                left = Expr::Pow(box left, box Expr::Num(n));
+                continue;
+            }
+            match self.current_token.infix_op() {
+                Some(op) if op.precedence > prec => left = self.parse_infix_op(left, op)?,
+                _ => break,
             }
-
-            let right = self.parse_infix_op(left.clone())?;
-            left = right;
         }
         Ok(left)
     }
 
-    /// Parses an expression with an infix operator. Takes the left expression and
-    /// then parses the right before returning an `Expr` that combines the two.
-    fn parse_infix_op(&mut self, left: Expr) -> Result<Expr, ParseError> {
-        match self.current_token {
-            Token::Add => {
+    /// Parses an expression with an infix or postfix operator. Takes the left
+    /// expression and the operator, then parses the right (if there is one)
+    /// before returning an `Expr` that combines the two.
+    fn parse_infix_op(&mut self, left: Expr, op: &Operator) -> Result<Expr, ParseError> {
+        match op.fixity {
+            Fixity::Infix(build) => {
                 // Consume the token
                 self.next_token()?;
-                // Get the right expression
-                let right = self.parse_expr(Precedence::Sum)?;
-                // Return an Add of the left and right
-                Ok(Expr::Add(box left, box right))
-            }
-            Token::Sub => {
-                self.next_token()?;
-                let right = self.parse_expr(Precedence::Sum)?;
-                Ok(Expr::Sub(box left, box right))
-            }
-            Token::Mul => {
-                self.next_token()?;
-                let right = self.parse_expr(Precedence::Product)?;
-                Ok(Expr::Mul(box left, box right))
-            }
-            Token::Div => {
-                self.next_token()?;
-                let right = self.parse_expr(Precedence::Product)?;
-                Ok(Expr::Div(box left, box right))
+                // Get the right expression, letting it take operators of
+                // equal precedence if they group to the right.
+                let prec = match op.assoc {
+                    Assoc::Left => op.precedence,
+                    Assoc::Right => op.precedence.below(),
+                };
+                let right = self.parse_expr(prec)?;
+                Ok(build(box left, box right))
             }
-            Token::Pow => {
+            Fixity::Postfix(build) => {
                 self.next_token()?;
-                let right = self.parse_expr(Precedence::Power)?;
-                Ok(Expr::Pow(box left, box right))
+                Ok(build(box left))
             }
-            _ => Err(ParseError::InvalidInput(format!(
-                "parse_infix_op: Expected infix_op, got {}",
-                self.current_token
-            ))),
+            Fixity::Prefix(_) => Err(self.invalid_input()),
         }
     }
 
@@ -110,54 +147,72 @@ impl<'a> Parser<'a> {
     /// an entire sub expression.
     fn parse_atom(&mut self) -> Result<Expr, ParseError> {
         let token = self.current_token.clone();
-        match token {
-            Token::Sub => {
+        if let Some(op) = token.prefix_op() {
+            if let Fixity::Prefix(build) = op.fixity {
                 self.next_token()?;
-                let expr = self.parse_expr(Precedence::Prefix)?;
-                Ok(Expr::Neg(box expr))
+                let expr = self.parse_expr(op.precedence)?;
+                return Ok(build(box expr));
             }
+        }
+
+        match token {
             Token::Num(i) => {
                 self.next_token()?;
                 match self.current_token {
-                    Token::LParen | Token::Func(_) | Token::Ident(_) => {
-                        let right = self.parse_expr(Precedence::Product)?;
-                        return Ok(Expr::Mul(box Expr::Num(i), box right));
+                    Token::LParen | Token::Func(_) | Token::Ident(_) | Token::History(_) => {
+                        let right = self.parse_expr(Precedence::PRODUCT)?;
+                        Ok(Expr::Mul(box Expr::Num(i), box right))
                     }
                     _ => Ok(Expr::Num(i)),
                 }
             }
+            Token::History(n) => {
+                let written = self.lexer.text(self.current_span).to_string();
+                self.next_token()?;
+                Ok(Expr::History(n, written))
+            }
             Token::Func(f) => {
                 self.next_token()?;
-                let expr = self.parse_expr(Precedence::Function)?;
-                Ok(Self::function_expr(f, expr))
+                if self.current_token != Token::LParen {
+                    // Without parens, a function takes a single argument.
+                    let expr = self.parse_expr(Precedence::FUNCTION)?;
+                    return Ok(Expr::Call(f, vec![expr]));
+                }
+
+                let call = Expr::Call(f, self.parse_args()?);
+                self.parse_call_suffix(call)
             }
             Token::Ident(ident) => {
                 self.next_token()?;
                 match self.current_token {
                     Token::Equals => {
                         self.next_token()?;
-                        let expr = self.parse_expr(Precedence::Assign)?;
-                        return Ok(Expr::Assign(ident, box expr));
+                        let expr = self.parse_expr(Precedence::LOWEST)?;
+                        Ok(Expr::Assign(ident, box expr))
+                    }
+                    Token::LParen if self.is_call(&ident) => {
+                        let call = Expr::Apply(ident, self.parse_args()?);
+                        self.parse_call_suffix(call)
                     }
                     Token::LParen | Token::Num(_) | Token::Func(_) => {
-                        let right = self.parse_expr(Precedence::Product)?;
-                        return Ok(Expr::Mul(box Expr::Ident(ident), box right));
+                        let right = self.parse_expr(Precedence::PRODUCT)?;
+                        Ok(Expr::Mul(box Expr::Ident(ident), box right))
                     }
                     _ => Ok(Expr::Ident(ident)),
                 }
             }
             Token::LParen => {
                 self.next_token()?;
-                let expr = self.parse_expr(Precedence::Lowest)?;
+                let expr = self.parse_expr(Precedence::LOWEST)?;
                 self.expect(Token::RParen)?;
                 if self.current_token == Token::LParen {
-                    let right = self.parse_expr(Precedence::Product)?;
+                    let right = self.parse_expr(Precedence::PRODUCT)?;
                     return Ok(Expr::Mul(box expr, box right));
                 }
 
                 Ok(expr)
             }
-            _ => Err(ParseError::UnknownAtom(format!("Unknown atom: {}", token))),
+            _ => Err(ParseError::UnknownAtom(token, self.current_span)),
         }
     }
 
@@ -168,40 +223,140 @@ impl<'a> Parser<'a> {
             self.next_token()?;
             Ok(())
         } else {
-            Err(ParseError::ExpectErr(format!(
-                "Expected {}, got {}",
-                expected, self.current_token
-            )))
+            Err(ParseError::ExpectErr(
+                expected,
+                self.current_token.clone(),
+                self.current_span,
+            ))
         }
     }
 
-    // Takes a Func::Func and an expression and
-    // returns the corresponding function expr.
-    fn function_expr(f: Func, e: Expr) -> Expr {
-        match f {
-            Func::Abs => Expr::Abs(box e),
-            Func::Floor => Expr::Floor(box e),
-            Func::Log => Expr::Log(box e),
-            Func::Ln => Expr::Ln(box e),
-            Func::Sin => Expr::Sin(box e),
-            Func::Cos => Expr::Cos(box e),
-            Func::Tan => Expr::Tan(box e),
-            Func::Arcsin => Expr::Arcsin(box e),
-            Func::Arccos => Expr::Arccos(box e),
-            Func::Arctan => Expr::Arctan(box e),
+    /// Returns a `ParseError` for the current `Token` appearing where it
+    /// is not allowed.
+    fn invalid_input(&self) -> ParseError {
+        ParseError::InvalidInput(self.current_token.clone(), self.current_span)
+    }
+
+    /// Returns whether the input starts with a function definition, that is,
+    /// a name and a parenthesized list followed by `=`.
+    fn at_definition(&self) -> bool {
+        match (&self.current_token, &self.peek_token) {
+            (Token::Ident(_), Token::LParen) => {}
+            _ => return false,
         }
+
+        let mut lexer = self.lexer.clone();
+        let mut depth = 1;
+        while depth > 0 {
+            match lexer.next_token() {
+                Ok((Token::LParen, _)) => depth += 1,
+                Ok((Token::RParen, _)) => depth -= 1,
+                Ok((Token::Eof, _)) | Err(_) => return false,
+                _ => {}
+            }
+        }
+        matches!(lexer.next_token(), Ok((Token::Equals, _)))
     }
-}
 
-// Error handling will need to be improved.
+    /// Parses a user function definition such as `f(x, y) = x^2 + y`.
+    fn parse_definition(&mut self) -> Result<Expr, ParseError> {
+        let name = match self.current_token {
+            Token::Ident(ref name) => name.clone(),
+            _ => return Err(self.invalid_input()),
+        };
+        self.next_token()?;
+        self.expect(Token::LParen)?;
+
+        let mut params: Vec<String> = vec![];
+        while self.current_token != Token::RParen {
+            match self.current_token {
+                Token::Ident(ref param) if !params.contains(param) => params.push(param.clone()),
+                _ => {
+                    let token = self.current_token.clone();
+                    return Err(ParseError::InvalidParam(token, self.current_span));
+                }
+            }
+            self.next_token()?;
+            if self.current_token != Token::Comma {
+                break;
+            }
+            self.next_token()?;
+        }
+        self.expect(Token::RParen)?;
+        self.expect(Token::Equals)?;
+
+        // The function may call itself, while its parameters shadow
+        // any functions of the same name.
+        let outer = self.functions.clone();
+        self.functions.insert(name.clone());
+        for param in params.iter() {
+            self.functions.remove(param);
+        }
+        self.params = Some(params);
+        let body = self.parse_expr(Precedence::LOWEST);
+        self.functions = outer;
+        let params = self.params.take().unwrap_or_default();
+
+        Ok(Expr::Function(name, params, box body?))
+    }
+
+    /// Parses what may follow a function call, which is just implicit
+    /// multiplication by a parenthesized expression, as in `sin(x)(1 + y)`.
+    fn parse_call_suffix(&mut self, call: Expr) -> Result<Expr, ParseError> {
+        if self.current_token == Token::LParen {
+            let right = self.parse_expr(Precedence::PRODUCT)?;
+            return Ok(Expr::Mul(box call, box right));
+        }
+
+        Ok(call)
+    }
+
+    /// Parses a parenthesized, comma-separated list of function arguments.
+    fn parse_args(&mut self) -> Result<Vec<Expr>, ParseError> {
+        self.expect(Token::LParen)?;
+        let mut args = vec![];
+        if self.current_token == Token::RParen {
+            self.next_token()?;
+            return Ok(args);
+        }
+
+        loop {
+            args.push(self.parse_expr(Precedence::LOWEST)?);
+            if self.current_token != Token::Comma {
+                break;
+            }
+            self.next_token()?;
+        }
+        self.expect(Token::RParen)?;
+        Ok(args)
+    }
+}
 
 #[derive(Debug)]
 /// Defines the various errors that can occur during parsing.
+/// Every error carries the `Span` of the offending input.
 pub enum ParseError {
-    ExpectErr(String),
-    UnknownAtom(String),
-    InvalidInput(String),
-    NoneError(String),
+    /// The expected `Token`, followed by the one that was found instead.
+    ExpectErr(Token, Token, Span),
+    UnknownAtom(Token, Span),
+    InvalidInput(Token, Span),
+    InvalidParam(Token, Span),
+    LexErr(LexError),
+}
+
+impl ParseError {
+    /// Returns the `Span` of the offending input.
+    pub fn span(&self) -> Span {
+        use self::ParseError::*;
+
+        match *self {
+            ExpectErr(_, _, span) => span,
+            UnknownAtom(_, span) => span,
+            InvalidInput(_, span) => span,
+            InvalidParam(_, span) => span,
+            LexErr(ref e) => e.span(),
+        }
+    }
 }
 
 impl fmt::Display for ParseError {
@@ -209,10 +364,13 @@ impl fmt::Display for ParseError {
         use self::ParseError::*;
 
         match *self {
-            ExpectErr(ref e) => write!(f, "Error: {}", e),
-            UnknownAtom(ref e) => write!(f, "Error: {}", e),
-            InvalidInput(ref e) => write!(f, "Error: {}", e),
-            NoneError(ref e) => write!(f, "Error: {}", e),
+            ExpectErr(ref expected, ref found, _) => {
+                write!(f, "Expected {}, got {}", expected, found)
+            }
+            UnknownAtom(ref token, _) => write!(f, "Unknown atom: {}", token),
+            InvalidInput(ref token, _) => write!(f, "Unexpected {}", token),
+            InvalidParam(ref token, _) => write!(f, "Invalid parameter: {}", token),
+            LexErr(ref e) => write!(f, "{}", e),
         }
     }
 }
@@ -222,23 +380,25 @@ impl error::Error for ParseError {
         use self::ParseError::*;
 
         match *self {
-            ExpectErr(ref e) => e,
-            UnknownAtom(ref e) => e,
-            InvalidInput(ref e) => e,
-            NoneError(ref e) => e,
+            ExpectErr(..) => "unexpected token",
+            UnknownAtom(..) => "unknown atom",
+            InvalidInput(..) => "invalid input",
+            InvalidParam(..) => "invalid parameter",
+            LexErr(_) => "lexing error",
         }
     }
 }
 
-impl From<NoneError> for ParseError {
-    fn from(_err: NoneError) -> Self {
-        ParseError::NoneError(String::from("Invalid character entered."))
+impl From<LexError> for ParseError {
+    fn from(err: LexError) -> Self {
+        ParseError::LexErr(err)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::Func;
 
     #[test]
     fn basic_add() {
@@ -281,7 +441,10 @@ mod tests {
     #[test]
     fn basic_func() {
         let mut parser = Parser::new("sin(1 + 1)").unwrap();
-        let expected_expr = Expr::Sin(box Expr::Add(box Expr::Num(1.0), box Expr::Num(1.0)));
+        let expected_expr = Expr::Call(
+            Func::Sin,
+            vec![Expr::Add(box Expr::Num(1.0), box Expr::Num(1.0))],
+        );
         assert_eq!(parser.parse().unwrap(), expected_expr);
     }
 
@@ -290,11 +453,35 @@ mod tests {
         let mut parser = Parser::new("5sin(1 + 1)").unwrap();
         let expected_expr = Expr::Mul(
             box Expr::Num(5.0),
-            box Expr::Sin(box Expr::Add(box Expr::Num(1.0), box Expr::Num(1.0))),
+            box Expr::Call(
+                Func::Sin,
+                vec![Expr::Add(box Expr::Num(1.0), box Expr::Num(1.0))],
+            ),
         );
         assert_eq!(parser.parse().unwrap(), expected_expr);
     }
 
+    #[test]
+    fn multi_arg_func() {
+        let mut parser = Parser::new("max(1, 2 + 3, x)").unwrap();
+        let expected_expr = Expr::Call(
+            Func::Max,
+            vec![
+                Expr::Num(1.0),
+                Expr::Add(box Expr::Num(2.0), box Expr::Num(3.0)),
+                Expr::Ident("x".to_string()),
+            ],
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+    }
+
+    #[test]
+    fn func_without_parens() {
+        let mut parser = Parser::new("ln 2").unwrap();
+        let expected_expr = Expr::Call(Func::Ln, vec![Expr::Num(2.0)]);
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+    }
+
     #[test]
     fn constant() {
         let mut parser = Parser::new("pi").unwrap();
@@ -309,6 +496,143 @@ mod tests {
         assert_eq!(parser.parse().unwrap(), expected_expr);
     }
 
+    #[test]
+    fn function_definition() {
+        let mut parser = Parser::new("f(x, y) = x + f(y, x)").unwrap();
+        let expected_expr = Expr::Function(
+            "f".to_string(),
+            vec!["x".to_string(), "y".to_string()],
+            box Expr::Add(
+                box Expr::Ident("x".to_string()),
+                box Expr::Apply(
+                    "f".to_string(),
+                    vec![Expr::Ident("y".to_string()), Expr::Ident("x".to_string())],
+                ),
+            ),
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+
+        let err = Parser::new("f(x, 2) = x").unwrap().parse().unwrap_err();
+        assert_eq!(Span::new(5, 6), err.span());
+    }
+
+    #[test]
+    fn function_call_vs_multiplication() {
+        let mut functions = HashSet::new();
+        functions.insert("f".to_string());
+
+        let mut parser = Parser::new("f(2) + a(2)").unwrap();
+        parser.set_functions(functions);
+        let expected_expr = Expr::Add(
+            box Expr::Apply("f".to_string(), vec![Expr::Num(2.0)]),
+            box Expr::Mul(box Expr::Ident("a".to_string()), box Expr::Num(2.0)),
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+
+        // In a definition, names other than parameters and constants are
+        // called even if not yet defined.
+        let mut parser = Parser::new("g(x) = a(x) + x(2) + pi(x)").unwrap();
+        let expected_expr = Expr::Function(
+            "g".to_string(),
+            vec!["x".to_string()],
+            box Expr::Add(
+                box Expr::Add(
+                    box Expr::Apply("a".to_string(), vec![Expr::Ident("x".to_string())]),
+                    box Expr::Mul(box Expr::Ident("x".to_string()), box Expr::Num(2.0)),
+                ),
+                box Expr::Mul(
+                    box Expr::Ident("pi".to_string()),
+                    box Expr::Ident("x".to_string()),
+                ),
+            ),
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+    }
+
+    #[test]
+    fn right_assoc_pow() {
+        let mut parser = Parser::new("2^3^2").unwrap();
+        let expected_expr = Expr::Pow(
+            box Expr::Num(2.0),
+            box Expr::Pow(box Expr::Num(3.0), box Expr::Num(2.0)),
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+    }
+
+    #[test]
+    fn left_assoc_sub() {
+        let mut parser = Parser::new("3-2-1").unwrap();
+        let expected_expr = Expr::Sub(
+            box Expr::Sub(box Expr::Num(3.0), box Expr::Num(2.0)),
+            box Expr::Num(1.0),
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+    }
+
+    #[test]
+    fn prefix_and_postfix() {
+        let mut parser = Parser::new("-2^3!").unwrap();
+        let expected_expr = Expr::Neg(box Expr::Pow(
+            box Expr::Num(2.0),
+            box Expr::Fact(box Expr::Num(3.0)),
+        ));
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+
+        let mut parser = Parser::new("2^-1").unwrap();
+        let expected_expr = Expr::Pow(box Expr::Num(2.0), box Expr::Neg(box Expr::Num(1.0)));
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+    }
+
+    #[test]
+    fn superscript_powers() {
+        let mut parser = Parser::new("-x²").unwrap();
+        let expected_expr = Expr::Neg(box Expr::Pow(
+            box Expr::Ident("x".to_string()),
+            box Expr::Num(2.0),
+        ));
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+
+        let mut parser = Parser::new("2^3²").unwrap();
+        let expected_expr = Expr::Pow(
+            box Expr::Num(2.0),
+            box Expr::Pow(box Expr::Num(3.0), box Expr::Num(2.0)),
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+
+        let mut parser = Parser::new("2x³").unwrap();
+        let expected_expr = Expr::Mul(
+            box Expr::Num(2.0),
+            box Expr::Pow(box Expr::Ident("x".to_string()), box Expr::Num(3.0)),
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+    }
+
+    #[test]
+    fn unit_conversion() {
+        let mut parser = Parser::new("x = 60 mph to m/s").unwrap();
+        let expected_expr = Expr::Assign(
+            "x".to_string(),
+            box Expr::Convert(
+                box Expr::Mul(box Expr::Num(60.0), box Expr::Ident("mph".to_string())),
+                box Expr::Div(
+                    box Expr::Ident("m".to_string()),
+                    box Expr::Ident("s".to_string()),
+                ),
+            ),
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+    }
+
+    #[test]
+    fn assignment_takes_whole_expr() {
+        let mut parser = Parser::new("a = 5 + 1").unwrap();
+        let expected_expr = Expr::Assign(
+            "a".to_string(),
+            box Expr::Add(box Expr::Num(5.0), box Expr::Num(1.0)),
+        );
+        assert_eq!(parser.parse().unwrap(), expected_expr);
+    }
+
     #[test]
     fn ord_of_ops() {
         let mut parser = Parser::new("1/(1+1)*1+2").unwrap();
@@ -324,4 +648,18 @@ mod tests {
         );
         assert_eq!(parser.parse().unwrap(), expected_expr);
     }
+
+    #[test]
+    fn error_spans() {
+        let err = Parser::new("3 + * 4").unwrap().parse().unwrap_err();
+        assert_eq!(Span::new(4, 5), err.span());
+
+        let err = Parser::new("1 + 2)").unwrap().parse().unwrap_err();
+        assert_eq!(Span::new(5, 6), err.span());
+
+        match Parser::new("4 $ 2") {
+            Err(ParseError::LexErr(e)) => assert_eq!(Span::new(2, 3), e.span()),
+            other => panic!("expected LexErr, got {:?}", other),
+        }
+    }
 }
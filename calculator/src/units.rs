@@ -0,0 +1,720 @@
+//! # Units
+//!
+//! Physical units, and quantities measured in them.
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use serde_derive::{Deserialize, Serialize};
+
+use std::error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+/// The powers of the SI base dimensions a quantity is made up of, in the
+/// order length, mass, time, current, temperature, amount and luminosity.
+pub struct Dimension([i32; 7]);
+
+impl Dimension {
+    pub const NONE: Dimension = Dimension([0, 0, 0, 0, 0, 0, 0]);
+    pub const LENGTH: Dimension = Dimension([1, 0, 0, 0, 0, 0, 0]);
+    pub const MASS: Dimension = Dimension([0, 1, 0, 0, 0, 0, 0]);
+    pub const TIME: Dimension = Dimension([0, 0, 1, 0, 0, 0, 0]);
+    pub const CURRENT: Dimension = Dimension([0, 0, 0, 1, 0, 0, 0]);
+    pub const TEMPERATURE: Dimension = Dimension([0, 0, 0, 0, 1, 0, 0]);
+    pub const AMOUNT: Dimension = Dimension([0, 0, 0, 0, 0, 1, 0]);
+    pub const LUMINOSITY: Dimension = Dimension([0, 0, 0, 0, 0, 0, 1]);
+    pub const AREA: Dimension = Dimension([2, 0, 0, 0, 0, 0, 0]);
+    pub const VOLUME: Dimension = Dimension([3, 0, 0, 0, 0, 0, 0]);
+    pub const SPEED: Dimension = Dimension([1, 0, -1, 0, 0, 0, 0]);
+    pub const ACCELERATION: Dimension = Dimension([1, 0, -2, 0, 0, 0, 0]);
+    pub const FREQUENCY: Dimension = Dimension([0, 0, -1, 0, 0, 0, 0]);
+    pub const FORCE: Dimension = Dimension([1, 1, -2, 0, 0, 0, 0]);
+    pub const ENERGY: Dimension = Dimension([2, 1, -2, 0, 0, 0, 0]);
+    pub const POWER: Dimension = Dimension([2, 1, -3, 0, 0, 0, 0]);
+    pub const PRESSURE: Dimension = Dimension([-1, 1, -2, 0, 0, 0, 0]);
+    pub const CHARGE: Dimension = Dimension([0, 0, 1, 1, 0, 0, 0]);
+    pub const VOLTAGE: Dimension = Dimension([2, 1, -3, -1, 0, 0, 0]);
+    pub const RESISTANCE: Dimension = Dimension([2, 1, -3, -2, 0, 0, 0]);
+
+    /// Returns the dimension of a product of quantities of this
+    /// dimension and `other`.
+    fn mul(self, other: Dimension) -> Dimension {
+        let mut powers = self.0;
+        for (power, other) in powers.iter_mut().zip(other.0.iter()) {
+            *power = power.saturating_add(*other);
+        }
+        Dimension(powers)
+    }
+
+    fn powi(self, n: i32) -> Dimension {
+        let mut powers = self.0;
+        for power in powers.iter_mut() {
+            *power = power.saturating_mul(n);
+        }
+        Dimension(powers)
+    }
+}
+
+/// Names for the dimensions that have one, used in error messages.
+const DIMENSION_NAMES: &[(Dimension, &str)] = &[
+    (Dimension::NONE, "a number"),
+    (Dimension::LENGTH, "length"),
+    (Dimension::MASS, "mass"),
+    (Dimension::TIME, "time"),
+    (Dimension::CURRENT, "current"),
+    (Dimension::TEMPERATURE, "temperature"),
+    (Dimension::AMOUNT, "amount of substance"),
+    (Dimension::LUMINOSITY, "luminous intensity"),
+    (Dimension::AREA, "area"),
+    (Dimension::VOLUME, "volume"),
+    (Dimension::SPEED, "speed"),
+    (Dimension::ACCELERATION, "acceleration"),
+    (Dimension::FREQUENCY, "frequency"),
+    (Dimension::FORCE, "force"),
+    (Dimension::ENERGY, "energy"),
+    (Dimension::POWER, "power"),
+    (Dimension::PRESSURE, "pressure"),
+    (Dimension::CHARGE, "charge"),
+    (Dimension::VOLTAGE, "voltage"),
+    (Dimension::RESISTANCE, "resistance"),
+];
+
+/// The symbols of the SI base units, which dimensions without a name
+/// are written out in.
+const BASE_SYMBOLS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((_, name)) = DIMENSION_NAMES.iter().find(|(dim, _)| dim == self) {
+            return write!(f, "{}", name);
+        }
+        let powers = BASE_SYMBOLS
+            .iter()
+            .zip(self.0.iter())
+            .map(|(symbol, &power)| (*symbol, power))
+            .filter(|&(_, power)| power != 0);
+        write!(f, "{}", write_powers(powers))
+    }
+}
+
+/// The largest power a unit can be raised to, which keeps the powers of
+/// dimensions well within range.
+pub const MAX_POWER: i32 = i8::MAX as i32;
+
+/// A unit in the registry. Units measuring the same dimension differ by a
+/// constant factor, so temperatures are only supported in kelvin.
+struct UnitDef {
+    /// What the unit is written out as, which SI prefixes can go in front of.
+    symbol: &'static str,
+    /// Other names the unit can be written as, which cannot take prefixes.
+    names: &'static [&'static str],
+    /// The size of the unit in SI base units.
+    scale: f64,
+    dim: Dimension,
+    prefixes: bool,
+}
+
+/// Shorthand for a `UnitDef`, to keep the registry readable.
+const fn def(
+    symbol: &'static str,
+    names: &'static [&'static str],
+    scale: f64,
+    dim: Dimension,
+    prefixes: bool,
+) -> UnitDef {
+    UnitDef {
+        symbol,
+        names,
+        scale,
+        dim,
+        prefixes,
+    }
+}
+
+/// The unit registry. `min` and `in` are taken by the minimum function and
+/// the conversion operator, so minutes and inches are only written out in full.
+const UNITS: &[UnitDef] = &[
+    // Length
+    def(
+        "m",
+        &["metre", "metres", "meter", "meters"],
+        1.0,
+        Dimension::LENGTH,
+        true,
+    ),
+    def("inch", &["inches"], 0.0254, Dimension::LENGTH, false),
+    def("ft", &["foot", "feet"], 0.3048, Dimension::LENGTH, false),
+    def("yd", &["yard", "yards"], 0.9144, Dimension::LENGTH, false),
+    def("mi", &["mile", "miles"], 1609.344, Dimension::LENGTH, false),
+    def("nmi", &[], 1852.0, Dimension::LENGTH, false),
+    def("au", &[], 149_597_870_700.0, Dimension::LENGTH, false),
+    def(
+        "ly",
+        &["lightyear", "lightyears"],
+        9_460_730_472_580_800.0,
+        Dimension::LENGTH,
+        false,
+    ),
+    // Mass
+    def("g", &["gram", "grams"], 1e-3, Dimension::MASS, true),
+    def("t", &["tonne", "tonnes"], 1000.0, Dimension::MASS, false),
+    def(
+        "lb",
+        &["lbs", "pound", "pounds"],
+        0.453_592_37,
+        Dimension::MASS,
+        false,
+    ),
+    def(
+        "oz",
+        &["ounce", "ounces"],
+        0.028_349_523_125,
+        Dimension::MASS,
+        false,
+    ),
+    // Time
+    def(
+        "s",
+        &["sec", "second", "seconds"],
+        1.0,
+        Dimension::TIME,
+        true,
+    ),
+    def("minute", &["minutes"], 60.0, Dimension::TIME, false),
+    def(
+        "h",
+        &["hr", "hour", "hours"],
+        3600.0,
+        Dimension::TIME,
+        false,
+    ),
+    def("day", &["days"], 86400.0, Dimension::TIME, false),
+    def("week", &["weeks"], 604_800.0, Dimension::TIME, false),
+    def(
+        "yr",
+        &["year", "years"],
+        31_557_600.0,
+        Dimension::TIME,
+        false,
+    ),
+    // The other base units
+    def(
+        "A",
+        &["amp", "amps", "ampere", "amperes"],
+        1.0,
+        Dimension::CURRENT,
+        true,
+    ),
+    def("K", &["kelvin"], 1.0, Dimension::TEMPERATURE, true),
+    def("mol", &["mole", "moles"], 1.0, Dimension::AMOUNT, true),
+    def("cd", &["candela"], 1.0, Dimension::LUMINOSITY, true),
+    // Area and volume
+    def("ha", &["hectare", "hectares"], 1e4, Dimension::AREA, false),
+    def("acre", &["acres"], 4_046.856_422_4, Dimension::AREA, false),
+    def(
+        "L",
+        &["l", "litre", "litres", "liter", "liters"],
+        1e-3,
+        Dimension::VOLUME,
+        true,
+    ),
+    def(
+        "gal",
+        &["gallon", "gallons"],
+        0.003_785_411_784,
+        Dimension::VOLUME,
+        false,
+    ),
+    // Speed
+    def("mph", &[], 0.447_04, Dimension::SPEED, false),
+    def("kph", &[], 1.0 / 3.6, Dimension::SPEED, false),
+    def(
+        "kn",
+        &["knot", "knots"],
+        1852.0 / 3600.0,
+        Dimension::SPEED,
+        false,
+    ),
+    // Derived units
+    def("Hz", &["hertz"], 1.0, Dimension::FREQUENCY, true),
+    def("N", &["newton", "newtons"], 1.0, Dimension::FORCE, true),
+    def("lbf", &[], 4.448_221_615_260_5, Dimension::FORCE, false),
+    def("J", &["joule", "joules"], 1.0, Dimension::ENERGY, true),
+    def(
+        "cal",
+        &["calorie", "calories"],
+        4.184,
+        Dimension::ENERGY,
+        true,
+    ),
+    def("eV", &[], 1.602_176_634e-19, Dimension::ENERGY, true),
+    def("Wh", &[], 3600.0, Dimension::ENERGY, true),
+    def("W", &["watt", "watts"], 1.0, Dimension::POWER, true),
+    def(
+        "hp",
+        &["horsepower"],
+        745.699_871_582_270_2,
+        Dimension::POWER,
+        false,
+    ),
+    def("Pa", &["pascal", "pascals"], 1.0, Dimension::PRESSURE, true),
+    def("bar", &[], 1e5, Dimension::PRESSURE, true),
+    def("atm", &[], 101_325.0, Dimension::PRESSURE, false),
+    def(
+        "psi",
+        &[],
+        6_894.757_293_168_361,
+        Dimension::PRESSURE,
+        false,
+    ),
+    def("C", &["coulomb", "coulombs"], 1.0, Dimension::CHARGE, true),
+    def("V", &["volt", "volts"], 1.0, Dimension::VOLTAGE, true),
+    def("ohm", &["ohms"], 1.0, Dimension::RESISTANCE, true),
+];
+
+/// The SI prefixes, which can go in front of the symbol of any unit that
+/// takes them, as in `km` or `mA`.
+const PREFIXES: &[(&str, f64)] = &[
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("da", 1e1),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+];
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+/// A unit from the registry, along with any prefix it was written with.
+pub struct Unit {
+    prefix: &'static str,
+    symbol: &'static str,
+    /// The size of the unit in SI base units, prefix included.
+    scale: f64,
+    dim: Dimension,
+}
+
+impl Unit {
+    /// Looks up a unit by name, such as `m`, `metres` or `km`.
+    pub fn find(name: &str) -> Option<Unit> {
+        let unit = |prefix, scale, def: &UnitDef| Unit {
+            prefix,
+            symbol: def.symbol,
+            scale: scale * def.scale,
+            dim: def.dim,
+        };
+        if let Some(def) = UNITS
+            .iter()
+            .find(|def| def.symbol == name || def.names.contains(&name))
+        {
+            return Some(unit("", 1.0, def));
+        }
+        PREFIXES.iter().find_map(|&(prefix, scale)| {
+            let symbol = name.strip_prefix(prefix)?;
+            let def = UNITS
+                .iter()
+                .find(|def| def.prefixes && def.symbol == symbol)?;
+            Some(unit(prefix, scale, def))
+        })
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.prefix, self.symbol)
+    }
+}
+
+/// Units are saved as they are written out, and looked up again on loading.
+impl Serialize for Unit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Unit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Unit::find(&name).ok_or_else(|| de::Error::custom(format!("Unknown unit: {}", name)))
+    }
+}
+
+/// Writes out a product of powers, such as `kg·m/s^2`.
+fn write_powers<'a, I>(powers: I) -> String
+where
+    I: Iterator<Item = (&'a str, i32)>,
+{
+    let join = |powers: &[(&str, i32)]| {
+        powers
+            .iter()
+            .map(|&(name, power)| match power {
+                1 => name.to_string(),
+                _ => format!("{}^{}", name, power),
+            })
+            .collect::<Vec<_>>()
+            .join("·")
+    };
+    let (above, below): (Vec<_>, Vec<_>) = powers.partition(|&(_, power)| power > 0);
+    // With nothing to divide, write `s^-1` rather than `1/s`.
+    if above.is_empty() || below.is_empty() {
+        return join(if above.is_empty() { &below } else { &above });
+    }
+    let below: Vec<_> = below
+        .into_iter()
+        .map(|(name, power)| (name, -power))
+        .collect();
+    if below.len() == 1 {
+        format!("{}/{}", join(&above), join(&below))
+    } else {
+        format!("{}/({})", join(&above), join(&below))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// A number with units, such as `9.81 m/s^2`. The number is always a float.
+pub struct Quantity {
+    value: f64,
+    /// The units and the powers they are raised to, none of which are zero.
+    units: Vec<(Unit, i32)>,
+}
+
+impl Quantity {
+    /// Returns a quantity of one of `unit`.
+    pub fn unit(unit: Unit) -> Self {
+        Quantity {
+            value: 1.0,
+            units: vec![(unit, 1)],
+        }
+    }
+
+    /// Returns a quantity with no units.
+    pub fn number(value: f64) -> Self {
+        Quantity {
+            value,
+            units: vec![],
+        }
+    }
+
+    /// Returns the number of units, as in 3 for `3 km`.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Returns a quantity with the same units as this one.
+    pub fn with_value(&self, value: f64) -> Self {
+        Quantity {
+            value,
+            units: self.units.clone(),
+        }
+    }
+
+    /// Writes out the units alone, as in `m/s^2`.
+    pub fn unit_names(&self) -> String {
+        let names: Vec<(String, i32)> = self
+            .units
+            .iter()
+            .map(|(unit, power)| (unit.to_string(), *power))
+            .collect();
+        write_powers(names.iter().map(|(name, power)| (name.as_str(), *power)))
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        self.units
+            .iter()
+            .fold(Dimension::NONE, |dim, (unit, power)| {
+                dim.mul(unit.dim.powi(*power))
+            })
+    }
+
+    /// Returns the size of this quantity's units in SI base units.
+    fn scale(&self) -> f64 {
+        self.units
+            .iter()
+            .map(|(unit, power)| unit.scale.powi(*power))
+            .product()
+    }
+
+    /// Returns whether this quantity is a plain number, which it is if
+    /// its units cancel out, as in `km/m`.
+    pub fn is_number(&self) -> bool {
+        self.dimension() == Dimension::NONE
+    }
+
+    /// Returns the value of this quantity as a number, if it has no dimension.
+    pub fn to_number(&self) -> Option<f64> {
+        if self.is_number() {
+            Some(self.value * self.scale())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value of `other` in the units of this quantity, if it
+    /// has the same dimension. `op` names the operation needing it.
+    pub fn value_of(&self, other: &Quantity, op: &'static str) -> Result<f64, UnitError> {
+        if self.dimension() != other.dimension() {
+            return Err(UnitError::Incompatible(
+                op,
+                self.dimension(),
+                other.dimension(),
+            ));
+        }
+        Ok(other.value * other.scale() / self.scale())
+    }
+
+    /// Combines two quantities of the same dimension with `f`, giving a
+    /// quantity in the units of this one.
+    pub fn combine(
+        &self,
+        other: &Quantity,
+        op: &'static str,
+        f: fn(f64, f64) -> f64,
+    ) -> Result<Quantity, UnitError> {
+        let other = self.value_of(other, op)?;
+        Ok(self.with_value(f(self.value, other)))
+    }
+
+    /// Converts this quantity to the units of `target`, which must be
+    /// one of a unit, as in `60 mph to m/s`.
+    pub fn convert(&self, target: &Quantity) -> Result<Quantity, UnitError> {
+        if target.value != 1.0 || target.units.is_empty() {
+            return Err(UnitError::NotAUnit);
+        }
+        let value = target.value_of(self, "to")?;
+        Ok(target.with_value(value))
+    }
+
+    /// Multiplies two quantities. Units of `other` measuring the same thing
+    /// as a unit of this quantity are converted to it, so that `km * m`
+    /// is in `km^2`.
+    pub fn mul(&self, other: &Quantity) -> Result<Quantity, UnitError> {
+        self.product(other, "*")
+    }
+
+    /// Multiplies two quantities for the operator `op`.
+    fn product(&self, other: &Quantity, op: &'static str) -> Result<Quantity, UnitError> {
+        let mut product = self.with_value(self.value * other.value);
+        for &(unit, power) in other.units.iter() {
+            let same = product.units.iter().position(|(u, _)| *u == unit);
+            let alike = same.or_else(|| {
+                product
+                    .units
+                    .iter()
+                    .position(|(u, _)| u.dim == unit.dim && u.dim != Dimension::NONE)
+            });
+            match alike {
+                Some(i) => {
+                    let (into, existing) = product.units[i];
+                    let power_of = existing + power;
+                    if power_of.abs() > MAX_POWER {
+                        return Err(UnitError::PowerTooLarge(op));
+                    }
+                    product.value *= (unit.scale / into.scale).powi(power);
+                    product.units[i] = (into, power_of);
+                }
+                None => product.units.push((unit, power)),
+            }
+        }
+        product.units.retain(|&(_, power)| power != 0);
+        Ok(product)
+    }
+
+    pub fn recip(&self) -> Quantity {
+        Quantity {
+            value: 1.0 / self.value,
+            units: self
+                .units
+                .iter()
+                .map(|&(unit, power)| (unit, -power))
+                .collect(),
+        }
+    }
+
+    pub fn div(&self, other: &Quantity) -> Result<Quantity, UnitError> {
+        self.product(&other.recip(), "/")
+    }
+
+    /// Raises this quantity to the power `exp`, as long as that leaves
+    /// each of its units raised to a whole power.
+    pub fn powf(&self, exp: f64) -> Result<Quantity, UnitError> {
+        let mut units = Vec::with_capacity(self.units.len());
+        for &(unit, power) in self.units.iter() {
+            let power = f64::from(power) * exp;
+            if power.fract() != 0.0 {
+                return Err(UnitError::FractionalPower(self.dimension()));
+            }
+            if power.abs() > f64::from(MAX_POWER) {
+                return Err(UnitError::PowerTooLarge("^"));
+            }
+            units.push((unit, power as i32));
+        }
+        units.retain(|&(_, power)| power != 0);
+        Ok(Quantity {
+            value: self.value.powf(exp),
+            units,
+        })
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit_names())
+    }
+}
+
+#[derive(Debug)]
+/// Defines the errors that can occur working with units.
+pub enum UnitError {
+    /// Contains the operator or function that was given quantities of
+    /// different dimensions, and those dimensions.
+    Incompatible(&'static str, Dimension, Dimension),
+    /// Contains the name of an operation that is only defined for plain
+    /// numbers, and the dimension of the quantity it was given.
+    NotANumber(String, Dimension),
+    /// A power that would leave a unit raised to a fraction, as in `(2 m)^0.5`.
+    FractionalPower(Dimension),
+    /// Contains the operator that would have left a unit raised to a power
+    /// larger than `MAX_POWER`.
+    PowerTooLarge(&'static str),
+    /// Something other than a unit was given to convert to.
+    NotAUnit,
+}
+
+impl UnitError {
+    /// Returns the operator or function the error occurred in.
+    pub fn op(&self) -> Option<&str> {
+        match *self {
+            UnitError::Incompatible(op, ..) => Some(op),
+            UnitError::NotANumber(ref op, _) => Some(op),
+            UnitError::FractionalPower(_) => Some("^"),
+            UnitError::PowerTooLarge(op) => Some(op),
+            UnitError::NotAUnit => Some("to"),
+        }
+    }
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::UnitError::*;
+
+        match *self {
+            Incompatible("+", lhs, rhs) => write!(f, "Cannot add {} to {}", rhs, lhs),
+            Incompatible("-", lhs, rhs) => write!(f, "Cannot subtract {} from {}", rhs, lhs),
+            Incompatible("to", target, dim) => write!(f, "Cannot convert {} to {}", dim, target),
+            Incompatible(op, lhs, rhs) => {
+                write!(f, "Cannot apply {} to {} and {}", op, lhs, rhs)
+            }
+            NotANumber(ref op, dim) => write!(f, "{} is not defined for {}", op, dim),
+            FractionalPower(dim) => write!(f, "Cannot raise {} to a fractional power", dim),
+            PowerTooLarge(_) => write!(f, "Units cannot be raised past the power {}", MAX_POWER),
+            NotAUnit => write!(f, "Can only convert to a unit, as in `60 mph to m/s`"),
+        }
+    }
+}
+
+impl error::Error for UnitError {
+    fn description(&self) -> &str {
+        use self::UnitError::*;
+
+        match *self {
+            Incompatible(..) => "incompatible units",
+            NotANumber(..) => "not defined for quantities with units",
+            FractionalPower(_) => "fractional power of a unit",
+            PowerTooLarge(_) => "power of a unit too large",
+            NotAUnit => "not a unit",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantity(name: &str) -> Quantity {
+        Quantity::unit(Unit::find(name).unwrap())
+    }
+
+    #[test]
+    fn registry() {
+        assert_eq!(Some(Dimension::LENGTH), Unit::find("km").map(|u| u.dim));
+        assert_eq!(Some(1e-6), Unit::find("mL").map(|u| u.scale));
+        assert_eq!(Some(100.0), Unit::find("hPa").map(|u| u.scale));
+        assert_eq!("kg", Unit::find("kg").unwrap().to_string());
+        assert_eq!("ft", Unit::find("feet").unwrap().to_string());
+        // Only unit symbols take prefixes.
+        assert_eq!(None, Unit::find("kmetre"));
+        assert_eq!(None, Unit::find("kft"));
+        assert_eq!(None, Unit::find("x"));
+
+        // Units are saved by name, so every unit must be found again by the
+        // name it is written out as.
+        for def in UNITS {
+            let prefixes: &[_] = if def.prefixes { PREFIXES } else { &[] };
+            for &(prefix, _) in [("", 1.0)].iter().chain(prefixes) {
+                let name = format!("{}{}", prefix, def.symbol);
+                let unit = Unit::find(&name).unwrap();
+                assert_eq!((prefix, def.symbol), (unit.prefix, unit.symbol));
+            }
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let km = quantity("km");
+        let m = quantity("m");
+        let length = km
+            .with_value(3.0)
+            .combine(&m.with_value(200.0), "+", |x, y| x + y);
+        assert_eq!("3.2 km", length.unwrap().to_string());
+        assert_eq!("1000 m^2", m.mul(&km).unwrap().to_string());
+        assert_eq!(Some(1000.0), km.div(&m).unwrap().to_number());
+
+        let accel = m.div(&quantity("s").powf(2.0).unwrap()).unwrap();
+        assert_eq!(Dimension::ACCELERATION, accel.dimension());
+        assert_eq!("5 m/s^2", accel.with_value(5.0).to_string());
+        let energy = quantity("kg").mul(&accel).unwrap().mul(&m).unwrap();
+        assert_eq!("1 kg·m^2/s^2", energy.to_string());
+        assert_eq!(
+            "2 Hz^-1",
+            quantity("Hz").recip().with_value(2.0).to_string()
+        );
+    }
+
+    #[test]
+    fn conversion() {
+        let speed = quantity("mph").with_value(60.0);
+        let target = quantity("m").div(&quantity("s")).unwrap();
+        let converted = speed.convert(&target).unwrap();
+        assert!((converted.value() - 26.8224).abs() < 1e-12);
+
+        let err = quantity("kg").convert(&quantity("m")).unwrap_err();
+        assert_eq!("Cannot convert mass to length", err.to_string());
+        let err = speed.convert(&target.with_value(2.0)).unwrap_err();
+        assert!(matches!(err, UnitError::NotAUnit));
+    }
+
+    #[test]
+    fn powers() {
+        let area = quantity("m").powf(2.0).unwrap();
+        assert_eq!("m", area.powf(0.5).unwrap().units[0].0.to_string());
+        assert!(quantity("m").powf(0.5).is_err());
+        let unnamed = Dimension::LENGTH.mul(Dimension::MASS.powi(2));
+        assert_eq!("m·kg^2", unnamed.to_string());
+
+        // Powers past `MAX_POWER` are errors, rather than overflowing the
+        // powers of the dimension.
+        let force = quantity("N").powf(65.0).unwrap();
+        assert_eq!(Dimension::FORCE.powi(65), force.dimension());
+        let big = quantity("m").powf(100.0).unwrap();
+        let err = big.mul(&big).unwrap_err();
+        assert!(matches!(err, UnitError::PowerTooLarge("*")));
+        assert!(big.div(&big.recip()).is_err());
+        assert!(quantity("m").powf(200.0).is_err());
+    }
+}
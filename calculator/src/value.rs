@@ -0,0 +1,637 @@
+//! # Value
+//!
+//! The numbers that expressions evaluate to.
+
+use crate::ast::AngleMode;
+use crate::decimal::{decimal_parts, Decimal};
+use crate::notation::{group_thousands, write_number, Notation};
+use crate::units::Quantity;
+
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use serde_derive::{Deserialize, Serialize};
+
+use std::fmt;
+use std::ops;
+use std::str::FromStr;
+
+/// The most bits an exact power or factorial may take up. Larger results
+/// are worked out as floats instead.
+pub const MAX_EXACT_BITS: u64 = 1 << 17;
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize, Default)]
+/// The kind of numbers calculations are carried out in.
+pub enum NumberMode {
+    #[default]
+    Real,
+    /// Operations with no real result, such as `sqrt(-1)`, give complex
+    /// numbers instead of NaN, and `i` is defined.
+    Complex,
+    /// Integers and fractions are kept exact through arithmetic, falling
+    /// back to floats for functions with irrational results.
+    Exact,
+    /// Numbers are decimals, worked out to a set number of significant digits.
+    Decimal,
+}
+
+impl fmt::Display for NumberMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NumberMode::Real => write!(f, "real"),
+            NumberMode::Complex => write!(f, "complex"),
+            NumberMode::Exact => write!(f, "exact"),
+            NumberMode::Decimal => write!(f, "decimal"),
+        }
+    }
+}
+
+impl FromStr for NumberMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "real" => Ok(NumberMode::Real),
+            "complex" => Ok(NumberMode::Complex),
+            "exact" => Ok(NumberMode::Exact),
+            "decimal" => Ok(NumberMode::Decimal),
+            _ => Err(format!("Unknown number mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize, Default)]
+/// How complex numbers are written out.
+pub enum ComplexForm {
+    /// `a + bi`
+    #[default]
+    Rectangular,
+    /// `r ∠ θ`, with the angle in the current angle mode.
+    Polar,
+}
+
+impl fmt::Display for ComplexForm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ComplexForm::Rectangular => write!(f, "rectangular"),
+            ComplexForm::Polar => write!(f, "polar"),
+        }
+    }
+}
+
+impl FromStr for ComplexForm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rect" | "rectangular" => Ok(ComplexForm::Rectangular),
+            "polar" => Ok(ComplexForm::Polar),
+            _ => Err(format!("Unknown complex form: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize, Default)]
+/// How exact fractions are written out.
+pub enum FractionForm {
+    /// `3/2`
+    #[default]
+    Improper,
+    /// `1 1/2`
+    Mixed,
+    /// `1.5`
+    Decimal,
+}
+
+impl fmt::Display for FractionForm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FractionForm::Improper => write!(f, "improper"),
+            FractionForm::Mixed => write!(f, "mixed"),
+            FractionForm::Decimal => write!(f, "decimal"),
+        }
+    }
+}
+
+impl FromStr for FractionForm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "improper" | "fraction" => Ok(FractionForm::Improper),
+            "mixed" => Ok(FractionForm::Mixed),
+            "decimal" => Ok(FractionForm::Decimal),
+            _ => Err(format!("Unknown fraction form: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Default, Serialize, Deserialize)]
+/// How results are written out.
+pub struct Format {
+    pub complex: ComplexForm,
+    pub fraction: FractionForm,
+    pub notation: Notation,
+    /// The number of decimal places in fixed notation, or of significant
+    /// digits in the others.
+    pub digits: Option<u32>,
+    /// Whether thousands are separated by commas, as in `1,000,000`.
+    pub separators: bool,
+}
+
+impl Format {
+    /// Writes out a float in the notation of this format. Infinities and
+    /// NaN are written out as they are in any notation.
+    fn real(&self, x: f64) -> String {
+        if x.is_finite() {
+            let (mantissa, exp) = decimal_parts(x);
+            if let Some(written) = write_number(&mantissa, exp, self.notation, self.digits) {
+                return written;
+            }
+        }
+        x.to_string()
+    }
+}
+
+/// Writes out a complex number as `a + bi`, with each part written out by
+/// `real`. Parts that are zero are left out, and so are imaginary parts
+/// of one, as in `1 + i`.
+fn write_complex<F>(z: Complex64, real: F) -> String
+where
+    F: Fn(f64) -> String,
+{
+    let im = z.im.abs();
+    let im = if im == 1.0 {
+        "i".to_string()
+    } else {
+        format!("{}i", real(im))
+    };
+    match (z.re == 0.0, z.im < 0.0) {
+        (true, false) => im,
+        (true, true) => format!("-{}", im),
+        (false, false) => format!("{} + {}", real(z.re), im),
+        (false, true) => format!("{} - {}", real(z.re), im),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+/// The result of evaluating an expression.
+pub enum Value {
+    Real(f64),
+    /// Always has a non-zero imaginary part, see `Value::complex`.
+    Complex(Complex64),
+    /// An exact integer or fraction, from exact mode.
+    Rational(BigRational),
+    /// A number from decimal mode.
+    Decimal(Decimal),
+    /// A number with units, which always has a dimension, see
+    /// `From<Quantity>`.
+    Quantity(Quantity),
+}
+
+impl Value {
+    /// Returns a `Value` for a complex number, which is real if its
+    /// imaginary part is zero, so that `i * i` is just -1.
+    pub fn complex(z: Complex64) -> Self {
+        if z.im == 0.0 {
+            Value::Real(z.re)
+        } else {
+            Value::Complex(z)
+        }
+    }
+
+    /// Returns the exact value of a number literal. The literal is taken
+    /// to be the shortest decimal that reads back as `x`, so that `0.1` is
+    /// exactly 1/10. Infinities and NaN stay as floats.
+    pub fn exact(x: f64) -> Self {
+        if !x.is_finite() {
+            return Value::Real(x);
+        }
+        let (digits, exp) = decimal_parts(x);
+        let ten = BigRational::from_integer(BigInt::from(10));
+        Value::Rational(BigRational::from_integer(digits) * ten.pow(exp as i32))
+    }
+
+    /// Returns the value of a number literal as a decimal, read the same
+    /// way as by `Value::exact`.
+    pub fn decimal(x: f64, precision: u32) -> Self {
+        Decimal::from_f64(x, precision).map_or(Value::Real(x), Value::Decimal)
+    }
+
+    /// Returns the number as a float if it is real.
+    pub fn as_real(&self) -> Option<f64> {
+        match *self {
+            Value::Real(x) => Some(x),
+            Value::Complex(_) => None,
+            Value::Rational(ref r) => Some(r.to_f64().unwrap_or(f64::NAN)),
+            Value::Decimal(ref d) => Some(d.to_f64()),
+            Value::Quantity(_) => None,
+        }
+    }
+
+    /// Returns the number as a decimal if it is one, or if it is exact.
+    pub fn to_decimal(&self, precision: u32) -> Option<Decimal> {
+        match *self {
+            Value::Decimal(ref d) => Some(d.clone()),
+            Value::Rational(ref r) => Some(Decimal::from_rational(r, precision)),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if it is exact.
+    pub fn as_rational(&self) -> Option<&BigRational> {
+        match *self {
+            Value::Rational(ref r) => Some(r),
+            _ => None,
+        }
+    }
+
+    pub fn to_complex(&self) -> Complex64 {
+        match *self {
+            Value::Complex(z) => z,
+            ref real => Complex64::new(real.as_real().unwrap(), 0.0),
+        }
+    }
+
+    pub fn is_nan(&self) -> bool {
+        match *self {
+            Value::Real(x) => x.is_nan(),
+            Value::Complex(z) => z.is_nan(),
+            Value::Rational(_) | Value::Decimal(_) => false,
+            Value::Quantity(ref q) => q.value().is_nan(),
+        }
+    }
+
+    pub fn has_units(&self) -> bool {
+        matches!(self, Value::Quantity(_))
+    }
+
+    /// Raises this value to the power `exp`. Exact values stay exact when
+    /// the result is rational. In complex mode real powers with no real
+    /// result, like `(-8)^(1/3)`, give the principal complex root.
+    pub fn pow(self, exp: Value, mode: NumberMode) -> Value {
+        if let (Value::Rational(x), Value::Rational(y)) = (&self, &exp) {
+            if let Some(exact) = exact_pow(x, y) {
+                return Value::Rational(exact);
+            }
+        }
+        if let Some((x, y)) = decimals(&self, &exp) {
+            if let Some(pow) = x.pow(&y) {
+                return Value::Decimal(pow);
+            }
+        }
+        if let (Some(x), Some(y)) = (self.as_real(), exp.as_real()) {
+            let real = x.powf(y);
+            if !real.is_nan() || mode != NumberMode::Complex || x.is_nan() || y.is_nan() {
+                return Value::Real(real);
+            }
+        }
+        let (z, w) = (self.to_complex(), exp.to_complex());
+        // Integer powers are done by repeated multiplication, which keeps
+        // `i^2` at exactly -1.
+        if w.im == 0.0 && w.re.fract() == 0.0 && w.re.abs() <= i32::MAX as f64 {
+            Value::complex(z.powi(w.re as i32))
+        } else {
+            Value::complex(z.powc(w))
+        }
+    }
+
+    /// Writes out the value in the given `Format`, with polar angles in
+    /// units of `angle`. Exact fractions are only written in the notation
+    /// of the format in decimal form, and numbers with units are written
+    /// with powers of ten rather than SI prefixes, which would read as part
+    /// of the unit.
+    pub fn format(&self, format: Format, angle: AngleMode) -> String {
+        let written = match *self {
+            Value::Real(x) => format.real(x),
+            Value::Complex(z) if format.complex == ComplexForm::Polar => {
+                let unit = match angle {
+                    AngleMode::Radians => "rad",
+                    AngleMode::Degrees => "deg",
+                    AngleMode::Gradians => "grad",
+                };
+                let arg = angle.from_radians(z.arg());
+                format!("{} ∠ {} {}", format.real(z.norm()), format.real(arg), unit)
+            }
+            Value::Complex(z) => write_complex(z, |x| format.real(x)),
+            Value::Rational(ref r) => match format.fraction {
+                FractionForm::Improper => r.to_string(),
+                FractionForm::Mixed if r.is_integer() || r.trunc().is_zero() => r.to_string(),
+                FractionForm::Mixed => format!("{} {}", r.trunc(), r.fract().abs()),
+                FractionForm::Decimal => format.real(self.as_real().unwrap()),
+            },
+            Value::Decimal(ref d) => {
+                let (mantissa, exp) = d.parts();
+                write_number(mantissa, exp, format.notation, format.digits)
+                    .unwrap_or_else(|| d.to_string())
+            }
+            Value::Quantity(ref q) => {
+                let format = match format.notation {
+                    Notation::Si => Format {
+                        notation: Notation::Engineering,
+                        ..format
+                    },
+                    _ => format,
+                };
+                format!("{} {}", format.real(q.value()), q.unit_names())
+            }
+        };
+        if format.separators {
+            group_thousands(&written)
+        } else {
+            written
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Real(x) => write!(f, "{}", x),
+            Value::Complex(z) => write!(f, "{}", write_complex(z, |x| x.to_string())),
+            Value::Rational(ref r) => write!(f, "{}", r),
+            Value::Decimal(ref d) => write!(f, "{}", d),
+            Value::Quantity(ref q) => write!(f, "{}", q),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Real(x)
+    }
+}
+
+/// Quantities whose units cancel out are plain numbers.
+impl From<Quantity> for Value {
+    fn from(q: Quantity) -> Self {
+        match q.to_number() {
+            Some(x) => Value::Real(x),
+            None => Value::Quantity(q),
+        }
+    }
+}
+
+impl PartialEq<Value> for f64 {
+    fn eq(&self, other: &Value) -> bool {
+        other.as_real() == Some(*self)
+    }
+}
+
+/// Returns `x^y` if it is rational and small enough to work out exactly.
+fn exact_pow(x: &BigRational, y: &BigRational) -> Option<BigRational> {
+    let base = if y.is_integer() {
+        x.clone()
+    } else {
+        exact_root(x, y.denom().to_u32()?)?
+    };
+    let exp = y.numer().to_i32()?;
+    if base.is_zero() && exp < 0 {
+        return None;
+    }
+    let bits = base.numer().bits().max(base.denom().bits());
+    if bits * u64::from(exp.unsigned_abs()) > MAX_EXACT_BITS {
+        return None;
+    }
+    Some(base.pow(exp))
+}
+
+/// Returns the `n`th root of `x` if it is rational.
+pub fn exact_root(x: &BigRational, n: u32) -> Option<BigRational> {
+    if x.is_negative() {
+        return None;
+    }
+    let root = |i: &BigInt| Some(i.nth_root(n)).filter(|r| r.pow(n) == *i);
+    Some(BigRational::new(root(x.numer())?, root(x.denom())?))
+}
+
+/// Applies an arithmetic operation to values that cannot be kept exact,
+/// as floats if both are real.
+fn inexact_op(
+    lhs: Value,
+    rhs: Value,
+    real: fn(f64, f64) -> f64,
+    complex: fn(Complex64, Complex64) -> Complex64,
+) -> Value {
+    match (lhs.as_real(), rhs.as_real()) {
+        (Some(x), Some(y)) => Value::Real(real(x, y)),
+        _ => Value::complex(complex(lhs.to_complex(), rhs.to_complex())),
+    }
+}
+
+/// Returns both values as decimals, if one is a decimal and the other is
+/// a decimal or exact.
+pub fn decimals(lhs: &Value, rhs: &Value) -> Option<(Decimal, Decimal)> {
+    let precision = match (lhs, rhs) {
+        (Value::Decimal(d), _) | (_, Value::Decimal(d)) => d.precision(),
+        _ => return None,
+    };
+    Some((lhs.to_decimal(precision)?, rhs.to_decimal(precision)?))
+}
+
+macro_rules! value_op {
+    ($trait:ident, $method:ident, $op:tt) => {
+        impl ops::$trait for Value {
+            type Output = Value;
+
+            fn $method(self, rhs: Value) -> Value {
+                match (self, rhs) {
+                    (Value::Rational(x), Value::Rational(y)) => Value::Rational(x $op y),
+                    // A decimal result out of range overflows as a float does.
+                    (lhs, rhs) => match decimals(&lhs, &rhs).and_then(|(x, y)| x.$method(&y)) {
+                        Some(val) => Value::Decimal(val),
+                        None => inexact_op(lhs, rhs, |x, y| x $op y, |x, y| x $op y),
+                    },
+                }
+            }
+        }
+    };
+}
+
+value_op!(Add, add, +);
+value_op!(Sub, sub, -);
+value_op!(Mul, mul, *);
+
+impl ops::Div for Value {
+    type Output = Value;
+
+    fn div(self, rhs: Value) -> Value {
+        match (self, rhs) {
+            // Dividing by zero gives an infinity or NaN, like it does for floats.
+            (Value::Rational(x), Value::Rational(y)) if !y.is_zero() => Value::Rational(x / y),
+            (lhs, rhs) => match decimals(&lhs, &rhs).and_then(|(x, y)| x.div(&y)) {
+                Some(quotient) => Value::Decimal(quotient),
+                None => inexact_op(lhs, rhs, |x, y| x / y, |x, y| x / y),
+            },
+        }
+    }
+}
+
+impl ops::Neg for Value {
+    type Output = Value;
+
+    fn neg(self) -> Value {
+        match self {
+            Value::Real(x) => Value::Real(-x),
+            Value::Complex(z) => Value::Complex(-z),
+            Value::Rational(r) => Value::Rational(-r),
+            Value::Decimal(d) => Value::Decimal(d.neg()),
+            Value::Quantity(q) => Value::Quantity(q.with_value(-q.value())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Unit;
+
+    #[test]
+    fn collapses_to_real() {
+        let i = Value::Complex(Complex64::i());
+        assert_eq!(Value::Real(-1.0), i.clone() * i.clone());
+        assert_eq!(Value::Real(-1.0), i.pow(Value::Real(2.0), NumberMode::Real));
+    }
+
+    #[test]
+    fn complex_pow() {
+        assert!(Value::Real(-8.0)
+            .pow(Value::Real(1.0 / 3.0), NumberMode::Real)
+            .is_nan());
+        match Value::Real(-8.0).pow(Value::Real(1.0 / 3.0), NumberMode::Complex) {
+            Value::Complex(z) => {
+                assert!((z.re - 1.0).abs() < 1e-12);
+                assert!((z.im - 3f64.sqrt()).abs() < 1e-12);
+            }
+            other => panic!("expected a complex root, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn display() {
+        let show = |re, im| Value::complex(Complex64::new(re, im)).to_string();
+        assert_eq!("3 + 4i", show(3.0, 4.0));
+        assert_eq!("3 - 4i", show(3.0, -4.0));
+        assert_eq!("-i", show(0.0, -1.0));
+        assert_eq!("2.5i", show(0.0, 2.5));
+        assert_eq!("1 + i", show(1.0, 1.0));
+        assert_eq!("7", show(7.0, 0.0));
+
+        let z = Value::complex(Complex64::new(0.0, 2.0));
+        let polar = Format {
+            complex: ComplexForm::Polar,
+            ..Format::default()
+        };
+        assert_eq!("2 ∠ 90 deg", z.format(polar, AngleMode::Degrees));
+        assert_eq!("2i", z.format(Format::default(), AngleMode::Degrees));
+    }
+
+    fn ratio(numer: i64, denom: i64) -> Value {
+        Value::Rational(BigRational::new(numer.into(), denom.into()))
+    }
+
+    #[test]
+    fn exact_literals() {
+        assert_eq!(ratio(1, 10), Value::exact(0.1));
+        assert_eq!(ratio(-1, 800), Value::exact(-1.25e-3));
+        assert_eq!(ratio(6022, 1), Value::exact(6022.0));
+        assert_eq!(Value::Real(f64::INFINITY), Value::exact(f64::INFINITY));
+    }
+
+    #[test]
+    fn exact_arithmetic() {
+        assert_eq!(ratio(1, 2), ratio(1, 3) + ratio(1, 6));
+        assert_eq!(
+            ratio(0, 1),
+            Value::exact(0.1) * ratio(3, 1) - Value::exact(0.3)
+        );
+        assert_eq!(Value::Real(f64::INFINITY), ratio(1, 1) / ratio(0, 1));
+        assert_eq!(Value::Real(0.75), ratio(1, 2) + Value::Real(0.25));
+
+        let exact = NumberMode::Exact;
+        assert_eq!(ratio(1, 1024), ratio(2, 1).pow(ratio(-10, 1), exact));
+        assert_eq!(ratio(4, 9), ratio(8, 27).pow(ratio(2, 3), exact));
+        assert_eq!(
+            Value::Real(2f64.sqrt()),
+            ratio(2, 1).pow(ratio(1, 2), exact)
+        );
+        assert_eq!(
+            Value::Real(f64::INFINITY),
+            ratio(0, 1).pow(ratio(-1, 1), exact)
+        );
+    }
+
+    #[test]
+    fn fraction_forms() {
+        let show = |val: Value, fraction| {
+            let format = Format {
+                fraction,
+                ..Format::default()
+            };
+            val.format(format, AngleMode::Radians)
+        };
+        assert_eq!("-7/2", show(ratio(-7, 2), FractionForm::Improper));
+        assert_eq!("-3 1/2", show(ratio(-7, 2), FractionForm::Mixed));
+        assert_eq!("1/2", show(ratio(1, 2), FractionForm::Mixed));
+        assert_eq!("4", show(ratio(8, 2), FractionForm::Mixed));
+        assert_eq!("-3.5", show(ratio(-7, 2), FractionForm::Decimal));
+    }
+
+    #[test]
+    fn notations() {
+        let show = |val: Value, notation, digits| {
+            let format = Format {
+                notation,
+                digits,
+                ..Format::default()
+            };
+            val.format(format, AngleMode::Radians)
+        };
+        assert_eq!("0.1", show(Value::Real(0.1), Notation::Shortest, None));
+        assert_eq!("0.100", show(Value::Real(0.1), Notation::Shortest, Some(3)));
+        assert_eq!(
+            "0.333",
+            show(Value::Real(1.0 / 3.0), Notation::Fixed, Some(3))
+        );
+        assert_eq!(
+            "inf",
+            show(Value::Real(f64::INFINITY), Notation::Fixed, None)
+        );
+        assert_eq!(
+            "1.50e0 - 2.00e-3i",
+            show(
+                Value::complex(Complex64::new(1.5, -0.002)),
+                Notation::Scientific,
+                Some(3)
+            )
+        );
+        assert_eq!("1/3", show(ratio(1, 3), Notation::Fixed, Some(2)));
+        assert_eq!(
+            "12.3e3",
+            show(Value::decimal(12345.0, 50), Notation::Engineering, Some(3))
+        );
+    }
+
+    #[test]
+    fn separators_and_prefixes() {
+        let show = |val: Value, notation| {
+            let format = Format {
+                notation,
+                separators: true,
+                ..Format::default()
+            };
+            val.format(format, AngleMode::Radians)
+        };
+        let shortest = Notation::Shortest;
+        assert_eq!("1,234,567.5", show(Value::Real(1234567.5), shortest));
+        assert_eq!("7,000,001/2", show(ratio(7_000_001, 2), shortest));
+        assert_eq!("1.5M", show(Value::Real(1.5e6), Notation::Si));
+        assert_eq!("22.5m", show(Value::Real(0.0225), Notation::Si));
+        let km = Quantity::unit(Unit::find("km").unwrap());
+        assert_eq!(
+            "1.5e3 km",
+            show(Value::Quantity(km.with_value(1500.0)), Notation::Si)
+        );
+    }
+}
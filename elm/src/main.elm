@@ -25,13 +25,14 @@ main =
 
 -- MODEL
 
+-- The log holds each calculation with either its result or its error.
 type alias Model = { log : List (String, String), input : String }
 
 -- UPDATE
 
 type Msg = 
     Clear
-  | GotCalc (Result Http.Error String)
+  | GotCalc (Result Http.Error (Result String String))
   | SetCalc String
   | SubmitForm
 
@@ -48,7 +49,7 @@ calculate input =
   Http.post
     { url = "/"
     , body = Http.jsonBody (calcEncoder input)
-    , expect = Http.expectJson GotCalc calcDecoder
+    , expect = Http.expectStringResponse GotCalc calcResponse
     }
 
 calcEncoder : String -> Encode.Value
@@ -56,17 +57,35 @@ calcEncoder calc =
   Encode.object
     [ ("calc", Encode.string calc) ]
 
-calcDecoder : Decoder String
-calcDecoder = 
-  Decode.string
+-- Results come back with the value written out, and invalid calculations
+-- with an error status and a message.
+calcResponse : Http.Response String -> Result Http.Error (Result String String)
+calcResponse response =
+  case response of
+    Http.GoodStatus_ _ body ->
+      decodeBody (Decode.map Ok (Decode.field "formatted" Decode.string)) body
+    Http.BadStatus_ _ body ->
+      decodeBody (Decode.map Err (Decode.at ["error", "message"] Decode.string)) body
+    Http.BadUrl_ url ->
+      Err (Http.BadUrl url)
+    Http.Timeout_ ->
+      Err Http.Timeout
+    Http.NetworkError_ ->
+      Err Http.NetworkError
+
+decodeBody : Decoder a -> String -> Result Http.Error a
+decodeBody decoder body =
+  Result.mapError (Decode.errorToString >> Http.BadBody) (Decode.decodeString decoder body)
 
 update : Msg -> Model -> (Model, Cmd Msg)
 update msg model =
   case msg of
     Clear  ->
       ((Model [] ""), Cmd.none)
-    GotCalc (Ok val) ->
-      ((Model (model.log ++ [(model.input, val)]) ""), Cmd.none)
+    GotCalc (Ok (Ok val)) ->
+      ((Model (model.log ++ [(model.input, "= " ++ val)]) ""), Cmd.none)
+    GotCalc (Ok (Err err)) ->
+      ((Model (model.log ++ [(model.input, "Error: " ++ err)]) ""), Cmd.none)
     GotCalc (Err _) ->
       ((Model (model.log) ""), Cmd.none)
     SetCalc input ->
@@ -97,7 +116,7 @@ view model =
       <| List.concatMap (\(i, v) -> 
       [ Grid.row [ Row.centerXs ] 
         [ Grid.col [ Col.xs6, Col.textAlign Text.alignXsLeft  ] [ text i ] 
-        , Grid.col [ Col.xs6, Col.textAlign Text.alignXsRight ] [ text ("= " ++ v) ] 
+        , Grid.col [ Col.xs6, Col.textAlign Text.alignXsRight ] [ text v ] 
         ]
       ]) model.log
     , Grid.row [ Row.centerXs ] [ Grid.col [ Col.xs4 ] [ calcForm model ] ]
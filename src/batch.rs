@@ -0,0 +1,171 @@
+//! # Batch
+//!
+//! Calculates without the REPL: expressions given on the command line, a
+//! script file, or lines piped to standard input.
+
+use calculator::calc::{Calculator, Value};
+
+use crate::commands::{self, Flow};
+
+use serde_derive::Serialize;
+
+use std::io;
+use std::str::FromStr;
+
+/// How the values of a batch are written out.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Output {
+    /// Each value on a line of its own, with errors on standard error.
+    Text,
+    /// A JSON object on each line, as in
+    /// `{"input":"1/4","value":0.25,"formatted":"0.25","error":null}`.
+    Json,
+    /// Comma separated `input,value,error` rows after a header row.
+    Csv,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Output::Text),
+            "json" => Ok(Output::Json),
+            "csv" => Ok(Output::Csv),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+/// The outcome of a line of JSON output, laid out like the answers of the
+/// web interface.
+#[derive(Serialize)]
+struct Record<'a> {
+    input: &'a str,
+    /// The value as a JSON number, or null if it is not a real number.
+    value: Option<f64>,
+    /// The value written out in the notation asked for. Empty for errors
+    /// and function definitions, which have no value.
+    formatted: String,
+    error: Option<String>,
+}
+
+/// Quotes a field of CSV output if it has commas, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl Output {
+    /// Writes out the value of `input`, or the error calculating it. In
+    /// text output there is nothing to write for a line with no value, and
+    /// errors are left to the caller.
+    fn write(self, calculator: &Calculator, input: &str, outcome: Result<Option<Value>, String>) {
+        let formatted = match outcome {
+            Ok(Some(ref val)) => calculator.format(val),
+            _ => String::new(),
+        };
+        match self {
+            Output::Text => {
+                if let Ok(Some(_)) = outcome {
+                    println!("{}", formatted);
+                }
+            }
+            Output::Json => {
+                let (value, error) = match outcome {
+                    Ok(val) => (val.and_then(|val| val.as_real()), None),
+                    Err(e) => (None, Some(e)),
+                };
+                let record = Record {
+                    input,
+                    value,
+                    formatted,
+                    error,
+                };
+                println!("{}", serde_json::to_string(&record).unwrap());
+            }
+            Output::Csv => {
+                let error = outcome.err().unwrap_or_default();
+                println!(
+                    "{},{},{}",
+                    csv_field(input),
+                    csv_field(&formatted),
+                    csv_field(&error)
+                );
+            }
+        }
+    }
+}
+
+/// Calculates each line in turn with `calculator`, writing out the value
+/// of each. Lines starting with `#` are run as commands, so that a script
+/// can change settings, except for those like `# note`, which are
+/// comments. In JSON and CSV output, what commands print goes to
+/// standard error, so as not to be mixed up with the values.
+///
+/// In text output, errors are printed to standard error with the line
+/// they are on if `source` names where the lines came from. Stops at the
+/// first error unless `keep_going` is set, and returns whether every line
+/// was calculated.
+pub fn calculate_lines<I>(
+    mut calculator: Calculator,
+    lines: I,
+    source: Option<&str>,
+    output: Output,
+    keep_going: bool,
+) -> bool
+where
+    I: IntoIterator<Item = io::Result<String>>,
+{
+    if output == Output::Csv {
+        println!("input,value,error");
+    }
+    let mut ok = true;
+    for (n, line) in lines.into_iter().enumerate() {
+        let (input, error) = match line {
+            Ok(line) => {
+                let line = line.trim();
+                if line.is_empty() || line == "#" || line.starts_with("# ") {
+                    continue;
+                }
+                if line.starts_with('#') {
+                    match commands::execute(&mut calculator, line) {
+                        Ok(Flow::Quit) => break,
+                        Ok(Flow::Continue(text)) => {
+                            match output {
+                                _ if text.is_empty() => {}
+                                Output::Text => println!("{}", text),
+                                Output::Json | Output::Csv => eprintln!("{}", text),
+                            }
+                            continue;
+                        }
+                        Err(e) => (line.to_string(), e),
+                    }
+                } else {
+                    match calculator.calculate(line) {
+                        Ok(val) => {
+                            output.write(&calculator, line, Ok(val));
+                            continue;
+                        }
+                        Err(e) => (line.to_string(), e.to_string()),
+                    }
+                }
+            }
+            Err(e) => (String::new(), e.to_string()),
+        };
+
+        match (output, source) {
+            (Output::Text, Some(source)) => eprintln!("{}:{}: Error: {}", source, n + 1, error),
+            (Output::Text, None) => eprintln!("Error: {}", error),
+            _ => output.write(&calculator, &input, Err(error)),
+        }
+        ok = false;
+        if !keep_going {
+            break;
+        }
+    }
+    ok
+}
@@ -0,0 +1,519 @@
+//! # Commands
+//!
+//! The commands of the REPL, which start with a `#`, as in `#angle deg`.
+//! Every command is listed in `COMMANDS`, along with its help text.
+
+use calculator::calc::{Calculator, Notation, NumberMode, DEFAULT_PLACES};
+
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+
+/// What the REPL does after running a command.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Flow {
+    /// Carries on, after printing out what the command had to say, which
+    /// may be nothing.
+    Continue(String),
+    Quit,
+}
+
+/// A REPL command, run by typing `#` and its name, then any arguments.
+pub struct Command {
+    pub name: &'static str,
+    /// How the arguments are written, as shown in help.
+    pub args: &'static str,
+    /// A one line description, listed by `#help`.
+    pub summary: &'static str,
+    /// A longer description, shown by `#help <command>`.
+    pub help: &'static str,
+    /// Runs the command with its arguments, which are trimmed and empty if
+    /// none were given. Its output and any error are printed out as they are.
+    run: fn(&mut Calculator, &str) -> Result<Flow, String>,
+}
+
+impl Command {
+    /// How the command is written, as in `#angle [deg|rad|grad]`.
+    fn usage(&self) -> String {
+        if self.args.is_empty() {
+            format!("#{}", self.name)
+        } else {
+            format!("#{} {}", self.name, self.args)
+        }
+    }
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        args: "[command]",
+        summary: "List the commands, or describe one of them",
+        help: "With no arguments, lists every command. Given the name of a command, with or \
+               without its #, describes what it does.",
+        run: help,
+    },
+    Command {
+        name: "quit",
+        args: "",
+        summary: "Leave the calculator",
+        help: "Leaves the calculator. Ctrl-C and Ctrl-D do the same.",
+        run: |_, _| Ok(Flow::Quit),
+    },
+    Command {
+        name: "vars",
+        args: "",
+        summary: "List the variables and functions",
+        help: "Lists every variable with its value, then every user defined function with its \
+               parameters.",
+        run: vars,
+    },
+    Command {
+        name: "constants",
+        args: "",
+        summary: "List the built in constants",
+        help: "Lists every built in constant defined in the current number mode, with its value \
+               and what it is. Physical constants have units, as in c * 2 s. Constants cannot \
+               be assigned to, except with #force.",
+        run: constants,
+    },
+    Command {
+        name: "force",
+        args: "<calculation>",
+        summary: "Calculate, letting variables take the names of constants",
+        help: "Calculates as usual, except that variables and functions can be given the names \
+               of built in constants, as in #force e = 2, which then stand in for the \
+               constants until they are removed with #unset.",
+        run: force,
+    },
+    Command {
+        name: "history",
+        args: "",
+        summary: "List the calculations so far",
+        help: "Lists every calculation that had a value, numbered from 1, along with the value \
+               it had at the time. Calculations can use these values: $3 or _3 is the third, \
+               $-2 the one two back, and ans the last.",
+        run: history,
+    },
+    Command {
+        name: "unset",
+        args: "<name>",
+        summary: "Remove a variable or function",
+        help: "Removes a variable or user defined function, so that the name is free to be \
+               used again, or refers to a unit or constant again.",
+        run: unset,
+    },
+    Command {
+        name: "clear",
+        args: "",
+        summary: "Remove every variable and function, and the history",
+        help: "Removes every variable and user defined function, and empties the history. The \
+               settings are kept.",
+        run: clear,
+    },
+    Command {
+        name: "undo",
+        args: "",
+        summary: "Undo the last calculation or change to the variables",
+        help: "Takes back the last calculation, along with anything it assigned or defined and \
+               its place in the history, or the last #unset or #clear. Settings are not undone. \
+               Up to 100 changes can be undone, though not those from before the calculator \
+               was started.",
+        run: |calculator, _| match calculator.undo() {
+            Some(undone) => lines(vec![format!("Undid {}", undone)]),
+            None => Err("There is nothing to undo".to_string()),
+        },
+    },
+    Command {
+        name: "redo",
+        args: "",
+        summary: "Redo what was last undone",
+        help: "Makes the change last taken back by #undo again. Once something else is \
+               calculated, what was undone cannot be redone.",
+        run: |calculator, _| match calculator.redo() {
+            Some(redone) => lines(vec![format!("Redid {}", redone)]),
+            None => Err("There is nothing to redo".to_string()),
+        },
+    },
+    Command {
+        name: "save",
+        args: "<file>",
+        summary: "Save the variables, functions, history and settings",
+        help: "Writes the variables, user defined functions, history and settings to a file, \
+               from which #load can restore them.",
+        run: save,
+    },
+    Command {
+        name: "load",
+        args: "<file>",
+        summary: "Restore what was saved with #save",
+        help: "Replaces the variables, user defined functions, history and settings with those \
+               in a file written by #save.",
+        run: load,
+    },
+    Command {
+        name: "angle",
+        args: "[deg|rad|grad]",
+        summary: "Show or change the angle mode",
+        help: "Sets the unit trig functions take, and inverse trig functions return, angles in. \
+               Angles can be given in any unit by writing it after them, as in sin(90 deg).",
+        run: |calculator, args| {
+            setting(
+                calculator,
+                args,
+                "Angle mode",
+                Calculator::angle_mode,
+                Calculator::set_angle_mode,
+            )
+        },
+    },
+    Command {
+        name: "mode",
+        args: "[real|complex|exact|decimal]",
+        summary: "Show or change the number mode",
+        help: "Sets the kind of numbers calculations are carried out in. Complex mode allows \
+               complex results, exact mode keeps fractions exact, and decimal mode works to \
+               the digits set by #precision.",
+        run: |calculator, args| {
+            setting(
+                calculator,
+                args,
+                "Number mode",
+                Calculator::number_mode,
+                Calculator::set_number_mode,
+            )
+        },
+    },
+    Command {
+        name: "precision",
+        args: "[digits]",
+        summary: "Show or change the digits decimal mode works to",
+        help: "Sets the number of significant digits decimal mode works to, and switches to \
+               decimal mode.",
+        run: precision,
+    },
+    Command {
+        name: "form",
+        args: "[rect|polar]",
+        summary: "Show or change how complex results are written",
+        help: "Sets whether complex results are written in rectangular form, as in 1 + 2i, or \
+               polar form, as in 2 ∠ 90 deg.",
+        run: |calculator, args| {
+            setting(
+                calculator,
+                args,
+                "Complex form",
+                Calculator::complex_form,
+                Calculator::set_complex_form,
+            )
+        },
+    },
+    Command {
+        name: "fraction",
+        args: "[improper|mixed|decimal]",
+        summary: "Show or change how exact results are written",
+        help: "Sets whether exact results are written as improper fractions, as in 7/2, mixed \
+               numbers, as in 3 1/2, or decimals.",
+        run: |calculator, args| {
+            setting(
+                calculator,
+                args,
+                "Fraction form",
+                Calculator::fraction_form,
+                Calculator::set_fraction_form,
+            )
+        },
+    },
+    Command {
+        name: "notation",
+        args: "[shortest|fixed|sci|eng|si]",
+        summary: "Show or change how numbers are written",
+        help: "Sets whether numbers are written with as few digits as read back as them, or \
+               the significant digits set by #digits, in fixed notation with the decimal \
+               places set by #digits, as in 3.14, in scientific notation, as in 3.14e2, in \
+               engineering notation, whose powers of ten are multiples of three, as in 314e0, \
+               or with SI prefixes, as in 12.5k.",
+        run: |calculator, args| {
+            setting(
+                calculator,
+                args,
+                "Notation",
+                Calculator::notation,
+                Calculator::set_notation,
+            )
+        },
+    },
+    Command {
+        name: "digits",
+        args: "[digits|all]",
+        summary: "Show or change how many digits numbers are written with",
+        help: "Sets the number of decimal places in fixed notation, and of significant digits \
+               otherwise, or with all, goes back to writing every digit.",
+        run: digits,
+    },
+    Command {
+        name: "separators",
+        args: "[on|off]",
+        summary: "Show or change whether thousands are separated",
+        help: "Sets whether thousands are separated by commas, as in 1,000,000.",
+        run: separators,
+    },
+];
+
+/// Looks up a command by name, which may start with its `#`.
+pub fn find(name: &str) -> Option<&'static Command> {
+    let name = name.strip_prefix('#').unwrap_or(name);
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// Runs a line of input starting with `#` as a command.
+pub fn execute(calculator: &mut Calculator, line: &str) -> Result<Flow, String> {
+    let line = line.trim();
+    let (name, args) = match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], line[end..].trim()),
+        None => (line, ""),
+    };
+    match find(name) {
+        Some(command) => (command.run)(calculator, args),
+        None => Err(format!(
+            "Unknown command: {}. Type #help for a list of commands.",
+            name
+        )),
+    }
+}
+
+/// Runs a line of input starting with `#` as a command, printing out its
+/// output or error.
+pub fn run(calculator: &mut Calculator, line: &str) -> Flow {
+    match execute(calculator, line) {
+        Ok(Flow::Continue(text)) => {
+            if !text.is_empty() {
+                println!("{}", text);
+            }
+            Flow::Continue(String::new())
+        }
+        Ok(Flow::Quit) => Flow::Quit,
+        Err(e) => {
+            println!("Error: {}", e);
+            Flow::Continue(String::new())
+        }
+    }
+}
+
+/// Carries on after a command that prints out `lines`.
+fn lines(lines: Vec<String>) -> Result<Flow, String> {
+    Ok(Flow::Continue(lines.join("\n")))
+}
+
+/// Listed by `#help` after the commands, as where names are read as units
+/// is not obvious.
+const UNITS_HELP: &str = "Names that are not variables or constants are read as units next to \
+                          something they measure, as in 2 t or x * m, and after to or in, as \
+                          in 90 minutes in h. Elsewhere they are undefined, as t is in t + 1. \
+                          Inches are written inch and minutes minute, since in is the \
+                          conversion keyword and min the minimum function.";
+
+fn help(_: &mut Calculator, topic: &str) -> Result<Flow, String> {
+    if topic.is_empty() {
+        let usages: Vec<String> = COMMANDS.iter().map(Command::usage).collect();
+        let width = usages.iter().map(|usage| usage.chars().count()).max();
+        return lines(
+            usages
+                .iter()
+                .zip(COMMANDS)
+                .map(|(usage, command)| {
+                    format!(
+                        "{:width$}  {}",
+                        usage,
+                        command.summary,
+                        width = width.unwrap_or(0)
+                    )
+                })
+                .chain(vec![String::new(), UNITS_HELP.to_string()])
+                .collect(),
+        );
+    }
+    let command = find(topic).ok_or_else(|| format!("Unknown command: {}", topic))?;
+    lines(vec![command.usage(), command.help.to_string()])
+}
+
+fn vars(calculator: &mut Calculator, _: &str) -> Result<Flow, String> {
+    let vars = calculator.variables();
+    let funcs = calculator.functions();
+    if vars.is_empty() && funcs.is_empty() {
+        return lines(vec!["No variables or functions are defined.".to_string()]);
+    }
+    let vars = vars
+        .iter()
+        .map(|(name, val)| format!("{} = {}", name, calculator.format(val)));
+    let funcs = funcs
+        .iter()
+        .map(|(name, params)| format!("{}({})", name, params.join(", ")));
+    lines(vars.chain(funcs).collect())
+}
+
+fn constants(calculator: &mut Calculator, _: &str) -> Result<Flow, String> {
+    let constants: Vec<(&str, &str, String)> = calculator
+        .constants()
+        .into_iter()
+        .map(|(name, description, val)| (name, description, calculator.format(&val)))
+        .collect();
+    let name_width = constants.iter().map(|c| c.0.chars().count()).max();
+    let value_width = constants.iter().map(|c| c.2.chars().count()).max();
+    lines(
+        constants
+            .iter()
+            .map(|(name, description, val)| {
+                format!(
+                    "{:name_width$} = {:value_width$}  {}",
+                    name,
+                    val,
+                    description,
+                    name_width = name_width.unwrap_or(0),
+                    value_width = value_width.unwrap_or(0)
+                )
+            })
+            .collect(),
+    )
+}
+
+fn force(calculator: &mut Calculator, calc: &str) -> Result<Flow, String> {
+    if calc.is_empty() {
+        return Err("Give a calculation, as in #force e = 2".to_string());
+    }
+    match calculator.force(calc) {
+        Ok(Some(val)) => lines(vec![calculator.format(&val)]),
+        Ok(None) => lines(vec![]),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn history(calculator: &mut Calculator, _: &str) -> Result<Flow, String> {
+    lines(
+        calculator
+            .get_log()
+            .iter()
+            .enumerate()
+            .map(|(n, (input, val))| format!("{}: {} = {}", n + 1, input, calculator.format(val)))
+            .collect(),
+    )
+}
+
+fn unset(calculator: &mut Calculator, name: &str) -> Result<Flow, String> {
+    if calculator.remove_variable(name).is_some() || calculator.remove_function(name).is_some() {
+        lines(vec![])
+    } else {
+        Err(format!("No variable or function is named {}", name))
+    }
+}
+
+fn clear(calculator: &mut Calculator, _: &str) -> Result<Flow, String> {
+    calculator.clear();
+    lines(vec![])
+}
+
+fn save(calculator: &mut Calculator, path: &str) -> Result<Flow, String> {
+    if path.is_empty() {
+        return Err("Give a file to save to, as in #save work.json".to_string());
+    }
+    write_state(calculator, Path::new(path))?;
+    lines(vec![format!("Saved to {}", path)])
+}
+
+fn load(calculator: &mut Calculator, path: &str) -> Result<Flow, String> {
+    if path.is_empty() {
+        return Err("Give a file to load from, as in #load work.json".to_string());
+    }
+    *calculator = read_state(Path::new(path))?;
+    lines(vec![format!("Loaded {}", path)])
+}
+
+/// Writes the variables, functions, history and settings of a calculator
+/// to a file.
+pub fn write_state(calculator: &Calculator, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    serde_json::to_writer(BufWriter::new(file), calculator)
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+/// Reads back a calculator written by `write_state`, checking its settings
+/// as they would be checked if they were changed by hand.
+pub fn read_state(path: &Path) -> Result<Calculator, String> {
+    let file = File::open(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let mut calculator: Calculator = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("{} is not a saved session: {}", path.display(), e))?;
+    let (precision, digits) = (calculator.precision(), calculator.digits());
+    calculator
+        .set_precision(precision)
+        .and_then(|_| calculator.set_digits(digits))
+        .map_err(|e| format!("{} is not a saved session: {}", path.display(), e))?;
+    Ok(calculator)
+}
+
+/// Shows the current value of a setting, after changing it if a new value
+/// is given.
+fn setting<T>(
+    calculator: &mut Calculator,
+    value: &str,
+    name: &str,
+    get: fn(&Calculator) -> T,
+    set: fn(&mut Calculator, T),
+) -> Result<Flow, String>
+where
+    T: FromStr<Err = String> + Display,
+{
+    if !value.is_empty() {
+        set(calculator, value.parse::<T>()?);
+    }
+    lines(vec![format!("{}: {}", name, get(calculator))])
+}
+
+/// Shows the number of digits numbers are written with, after changing it
+/// if a number of digits or `all` is given.
+fn digits(calculator: &mut Calculator, digits: &str) -> Result<Flow, String> {
+    match digits {
+        "" => {}
+        "all" => calculator.set_digits(None)?,
+        _ => {
+            let digits = digits
+                .parse::<u32>()
+                .map_err(|_| format!("Unknown number of digits: {}", digits))?;
+            calculator.set_digits(Some(digits))?;
+        }
+    }
+    let shown = match (calculator.digits(), calculator.notation()) {
+        (Some(digits), Notation::Fixed) => format!("{} decimal places", digits),
+        (Some(digits), _) => format!("{} significant digits", digits),
+        (None, Notation::Fixed) => format!("{} decimal places", DEFAULT_PLACES),
+        (None, _) => "all".to_string(),
+    };
+    lines(vec![format!("Digits: {}", shown)])
+}
+
+/// Shows whether thousands are separated, after changing it if `on` or
+/// `off` is given.
+fn separators(calculator: &mut Calculator, value: &str) -> Result<Flow, String> {
+    match value {
+        "" => {}
+        "on" => calculator.set_separators(true),
+        "off" => calculator.set_separators(false),
+        _ => return Err(format!("Unknown separators setting: {}", value)),
+    }
+    let shown = if calculator.separators() { "on" } else { "off" };
+    lines(vec![format!("Separators: {}", shown)])
+}
+
+/// Shows the working precision of decimal mode, after changing it and
+/// switching to decimal mode if a number of digits is given.
+fn precision(calculator: &mut Calculator, digits: &str) -> Result<Flow, String> {
+    if !digits.is_empty() {
+        let digits = digits
+            .parse::<u32>()
+            .map_err(|_| format!("Unknown precision: {}", digits))?;
+        calculator.set_precision(digits)?;
+        calculator.set_number_mode(NumberMode::Decimal);
+    }
+    let precision = calculator.precision();
+    lines(vec![format!("Precision: {} digits", precision)])
+}
@@ -0,0 +1,234 @@
+//! # Helper
+//!
+//! Tab completion, inline hints and syntax highlighting for the REPL's
+//! line editor.
+
+use calculator::calc::{Calculator, NumberMode, Style};
+
+use crate::commands::COMMANDS;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::{Context, Helper};
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+/// The longest line whose value is hinted at in exact and decimal modes,
+/// where arithmetic on large numbers is slow. Hints are worked out on every
+/// key press, so they must not hold up typing.
+const MAX_EXACT_HINT: usize = 80;
+
+/// Completes names and commands, hints at the value of the line being
+/// typed and highlights it, using the calculator of the REPL.
+pub struct ReplHelper {
+    calculator: Rc<RefCell<Calculator>>,
+}
+
+impl ReplHelper {
+    pub fn new(calculator: Rc<RefCell<Calculator>>) -> Self {
+        ReplHelper { calculator }
+    }
+}
+
+/// Returns where the name that ends at `pos` starts, which is `pos` itself
+/// if there is no such name. Digits before a name are a number being
+/// multiplied by it, as in `2pi`, while names may have digits and
+/// underscores after their first letter, as in `x1` or `k_B`.
+fn name_start(line: &str, pos: usize) -> usize {
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| c.is_alphabetic() || c.is_ascii_digit() || c == '_')
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let name = &line[start..pos];
+    pos - name.trim_start_matches(|c: char| c.is_ascii_digit()).len()
+}
+
+/// Returns the ANSI escape code that colors a style, if it is colored.
+fn color(style: Style) -> Option<&'static str> {
+    match style {
+        Style::Number => Some("36"),
+        Style::Operator => Some("33"),
+        Style::Paren => None,
+        Style::Function => Some("34"),
+        Style::Name => Some("32"),
+        // Unknown names are underlined rather than colored as errors, since
+        // they may be about to be assigned to.
+        Style::Unknown => Some("4"),
+        Style::Keyword => Some("35"),
+        Style::UnmatchedParen | Style::Invalid => Some("1;31"),
+    }
+}
+
+/// Returns the positions of the paren just before the cursor, or failing
+/// that just after it, and the paren that matches it.
+fn matching_parens(line: &str, pos: usize) -> Option<(usize, usize)> {
+    let bytes = line.as_bytes();
+    let at = [pos.checked_sub(1), Some(pos)]
+        .iter()
+        .flatten()
+        .copied()
+        .find(|&i| matches!(bytes.get(i), Some(b'(') | Some(b')')))?;
+    let mut depth = 0;
+    if bytes[at] == b'(' {
+        for (i, &b) in bytes.iter().enumerate().skip(at) {
+            depth += match b {
+                b'(' => 1,
+                b')' => -1,
+                _ => 0,
+            };
+            if depth == 0 {
+                return Some((at, i));
+            }
+        }
+    } else {
+        for (i, &b) in bytes.iter().enumerate().take(at + 1).rev() {
+            depth += match b {
+                b')' => 1,
+                b'(' => -1,
+                _ => 0,
+            };
+            if depth == 0 {
+                return Some((at, i));
+            }
+        }
+    }
+    None
+}
+
+/// Works out the value of `line`, formatted, for a hint. A panic in the
+/// calculator gives no hint, rather than ending the REPL or printing over
+/// the line being typed.
+fn preview(calculator: &Calculator, line: &str) -> Option<String> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let preview = panic::catch_unwind(AssertUnwindSafe(|| {
+        calculator
+            .preview(line)
+            .map(|val| val.map(|val| calculator.format(&val)))
+    }));
+    panic::set_hook(hook);
+    preview.ok()?.ok()?
+}
+
+fn pair(name: &str) -> Pair {
+    Pair {
+        display: name.to_string(),
+        replacement: name.to_string(),
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = name_start(line, pos);
+        let prefix = &line[start..pos];
+        let before = line[..start].trim();
+        // Commands are completed after the `#` that starts them, and after
+        // `#help`, which takes the name of one.
+        if before == "#" || before == "#help" {
+            let commands = COMMANDS
+                .iter()
+                .filter(|command| command.name.starts_with(prefix))
+                .map(|command| pair(command.name))
+                .collect();
+            return Ok((start, commands));
+        }
+        if prefix.is_empty() || before.starts_with('#') {
+            return Ok((pos, Vec::new()));
+        }
+        let names = self.calculator.borrow().names_starting_with(prefix);
+        Ok((start, names.iter().map(|name| pair(name)).collect()))
+    }
+}
+
+impl Hinter for ReplHelper {
+    /// Hints at the closing parens that will be added to the line and at
+    /// its value when the cursor is at its end, as in `>> 9sin(pi/2) = 9`,
+    /// without assigning any variables.
+    fn hint(&self, line: &str, pos: usize, _: &Context<'_>) -> Option<String> {
+        let calc = line.trim();
+        if pos < line.len() || calc.is_empty() || calc.starts_with('#') {
+            return None;
+        }
+        let mut hint = ")".repeat(Calculator::implied_parens(line));
+        let calculator = self.calculator.borrow();
+        let slow = matches!(
+            calculator.number_mode(),
+            NumberMode::Exact | NumberMode::Decimal
+        );
+        let formatted = if slow && calc.len() > MAX_EXACT_HINT {
+            None
+        } else {
+            preview(&calculator, line)
+        };
+        if let Some(formatted) = formatted {
+            // There is no need to repeat a number that is typed out in full.
+            if formatted != calc {
+                hint.push_str(" = ");
+                hint.push_str(&formatted);
+            }
+        }
+        if hint.is_empty() {
+            None
+        } else {
+            Some(hint)
+        }
+    }
+}
+
+impl Highlighter for ReplHelper {
+    /// Colors each token of the line by its style, and makes the paren at
+    /// the cursor and the one matching it bold.
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if line.trim_start().starts_with('#') {
+            return Cow::Borrowed(line);
+        }
+        let matching = matching_parens(line, pos);
+        let mut highlighted = String::with_capacity(line.len() * 2);
+        let mut end = 0;
+        for (span, style) in self.calculator.borrow().styles(line) {
+            highlighted.push_str(&line[end..span.start]);
+            let text = &line[span.start..span.end];
+            let bold = matches!(matching, Some((a, b)) if span.start == a || span.start == b);
+            let code = match (color(style), bold) {
+                (Some(color), true) => Some(format!("1;{}", color)),
+                (Some(color), false) => Some(color.to_string()),
+                (None, true) => Some("1".to_string()),
+                (None, false) => None,
+            };
+            match code {
+                Some(code) => highlighted.push_str(&format!("\x1b[{}m{}\x1b[0m", code, text)),
+                None => highlighted.push_str(text),
+            }
+            end = span.end;
+        }
+        highlighted.push_str(&line[end..]);
+        Cow::Owned(highlighted)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        // Hints, including the closing parens that will be added, are
+        // dimmed, so they are not mistaken for input.
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+
+    /// The line is highlighted again whenever the cursor moves, to follow
+    /// it with the matching parens.
+    fn highlight_char(&self, _: &str, _: usize) -> bool {
+        true
+    }
+}
+
+impl Helper for ReplHelper {}
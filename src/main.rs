@@ -1,30 +1,46 @@
 #![feature(proc_macro_hygiene, decl_macro)]
 
+mod batch;
+mod commands;
+mod helper;
 mod repl;
+mod session;
 mod web;
 
-use std::sync::RwLock;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+use std::time::Duration;
 
-use calculator::calc::Calculator;
-use clap::{App, SubCommand};
+use atty::Stream;
+use calculator::calc::{Calculator, Notation};
+use clap::{value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
 use rocket::http::Method;
 use rocket::routes;
 use rocket_contrib::templates::Template;
 use rocket_cors::{AllowedHeaders, AllowedOrigins};
 
+use crate::batch::{calculate_lines, Output};
 use crate::repl::repl;
+use crate::session::Sessions;
 use crate::web::*;
 
-fn run_rocket() {
+fn run_rocket(sessions: Sessions) {
     let allowed_origins = AllowedOrigins::all();
 
     // You can also deserialize this
     let cors = rocket_cors::CorsOptions {
         allowed_origins,
-        allowed_methods: vec![Method::Get, Method::Post, Method::Options]
-            .into_iter()
-            .map(From::from)
-            .collect(),
+        allowed_methods: vec![
+            Method::Get,
+            Method::Post,
+            Method::Put,
+            Method::Delete,
+            Method::Options,
+        ]
+        .into_iter()
+        .map(From::from)
+        .collect(),
         allowed_headers: AllowedHeaders::all(),
         allow_credentials: true,
         ..Default::default()
@@ -33,30 +49,226 @@ fn run_rocket() {
     .unwrap();
 
     rocket::ignite()
-        .manage(RwLock::new(Calculator::new()))
-        .mount("/", routes![get_index, calculate])
+        .manage(sessions)
+        .mount(
+            "/",
+            routes![
+                get_index,
+                calculate,
+                get_history,
+                get_variables,
+                get_variable,
+                put_variable,
+                delete_variable,
+                delete_session,
+                undo,
+                redo,
+                get_settings,
+                put_settings
+            ],
+        )
         .attach(Template::fairing())
         .attach(cors)
         .launch();
 }
 
-fn run_repl() {
-    repl();
+fn run_repl(restore: bool, save: bool) {
+    repl(restore, save);
+}
+
+/// How a batch is calculated and written out, as given on the command line.
+struct Batch {
+    calculator: Calculator,
+    output: Output,
+    keep_going: bool,
+}
+
+impl Batch {
+    /// Reads the options of `eval` or `run`, exiting if any are invalid.
+    fn from(matches: &ArgMatches) -> Self {
+        let mut calculator = Calculator::new();
+        if matches.is_present("notation") {
+            let notation = value_t!(matches, "notation", Notation).unwrap_or_else(|e| e.exit());
+            calculator.set_notation(notation);
+        }
+        if matches.is_present("digits") {
+            let digits = value_t!(matches, "digits", u32).unwrap_or_else(|e| e.exit());
+            if let Err(e) = calculator.set_digits(Some(digits)) {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        calculator.set_separators(matches.is_present("separators"));
+        Batch {
+            calculator,
+            output: value_t!(matches, "output", Output).unwrap_or_else(|e| e.exit()),
+            keep_going: matches.is_present("keep-going"),
+        }
+    }
+
+    /// Lines piped in with no subcommand are written out as text, and stop
+    /// at the first error.
+    fn piped() -> Self {
+        Batch {
+            calculator: Calculator::new(),
+            output: Output::Text,
+            keep_going: false,
+        }
+    }
+}
+
+/// Calculates every line, exiting with an error code if any of them fail.
+fn run_batch<I>(batch: Batch, lines: I, source: Option<&str>)
+where
+    I: IntoIterator<Item = io::Result<String>>,
+{
+    let Batch {
+        calculator,
+        output,
+        keep_going,
+    } = batch;
+    if !calculate_lines(calculator, lines, source, output, keep_going) {
+        process::exit(1);
+    }
+}
+
+fn run_script(batch: Batch, path: &str) {
+    if path == "-" {
+        let stdin = io::stdin();
+        return run_batch(batch, stdin.lock().lines(), Some("stdin"));
+    }
+    match File::open(path) {
+        Ok(file) => run_batch(batch, BufReader::new(file).lines(), Some(path)),
+        Err(e) => {
+            eprintln!("Error: Cannot read {}: {}", path, e);
+            process::exit(1);
+        }
+    }
 }
 
 fn main() {
+    let keep_going = Arg::with_name("keep-going")
+        .short("k")
+        .long("keep-going")
+        .help("Carries on past errors, though still exits with an error code");
+    let batch_args = [
+        keep_going,
+        Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .value_name("FORMAT")
+            .possible_values(&["text", "json", "csv"])
+            .default_value("text")
+            .help("Writes each value as text, a line of JSON or a row of CSV"),
+        Arg::with_name("notation")
+            .long("notation")
+            .value_name("NOTATION")
+            .possible_values(&[
+                "shortest",
+                "fixed",
+                "sci",
+                "scientific",
+                "eng",
+                "engineering",
+                "si",
+            ])
+            .help("How numbers are written: as briefly as they read back, or in a notation"),
+        Arg::with_name("separators")
+            .long("separators")
+            .help("Separates thousands with commas, as in 1,000,000"),
+        Arg::with_name("digits")
+            .long("digits")
+            .value_name("N")
+            .help("The decimal places of fixed notation, or the significant digits of the others"),
+    ];
     let matches = App::new("Rustulator")
         .version("0.9")
         .author("Liam Woodward <liamowoodward@gmail.com>")
         .about("Calculator REPL/Web interface")
-        .subcommand(SubCommand::with_name("repl").about("Starts the Rustulator repl"))
-        .subcommand(SubCommand::with_name("web").about("Starts the Rustulator web interface"))
+        .subcommand(
+            SubCommand::with_name("repl")
+                .about("Starts the Rustulator repl")
+                .arg(
+                    Arg::with_name("clean")
+                        .long("clean")
+                        .help("Starts without the variables, functions and history of last time"),
+                )
+                .arg(
+                    Arg::with_name("no-save")
+                        .long("no-save")
+                        .help("Doesn't keep the variables, functions and history for next time"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("eval")
+                .about("Calculates expressions and prints their values")
+                // So that `rustulator eval -3+4` is not read as a flag.
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("expressions")
+                        .value_name("EXPRESSION")
+                        .required(true)
+                        .multiple(true)
+                        .help("The expressions, calculated in order with the same variables"),
+                )
+                .args(&batch_args),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Calculates a script line by line and prints the values")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .required(true)
+                        .help("The script, or - to read standard input"),
+                )
+                .args(&batch_args),
+        )
+        .subcommand(
+            SubCommand::with_name("web")
+                .about("Starts the Rustulator web interface")
+                .arg(
+                    Arg::with_name("max-sessions")
+                        .long("max-sessions")
+                        .value_name("N")
+                        .default_value("1000")
+                        .help("How many clients can have a calculator at once"),
+                )
+                .arg(
+                    Arg::with_name("session-timeout")
+                        .long("session-timeout")
+                        .value_name("MINUTES")
+                        .default_value("60")
+                        .help("How long a client's calculator is kept after its last use"),
+                ),
+        )
         .get_matches();
 
-    if let Some(_) = matches.subcommand_matches("repl") {
-        run_repl();
+    if let Some(repl) = matches.subcommand_matches("repl") {
+        run_repl(!repl.is_present("clean"), !repl.is_present("no-save"));
+    }
+    if let Some(eval) = matches.subcommand_matches("eval") {
+        let exprs = eval
+            .values_of("expressions")
+            .into_iter()
+            .flatten()
+            .map(|expr| Ok(expr.to_string()));
+        run_batch(Batch::from(eval), exprs, None);
+    }
+    if let Some(run) = matches.subcommand_matches("run") {
+        let path = run.value_of("file").unwrap_or("-");
+        run_script(Batch::from(run), path);
+    }
+    // Calculations piped in are calculated as a script.
+    if matches.subcommand_name().is_none() && !atty::is(Stream::Stdin) {
+        run_script(Batch::piped(), "-");
     }
-    if let Some(_) = matches.subcommand_matches("web") {
-        run_rocket();
+    if let Some(web) = matches.subcommand_matches("web") {
+        let max_sessions = value_t!(web, "max-sessions", usize).unwrap_or_else(|e| e.exit());
+        let timeout = value_t!(web, "session-timeout", u64).unwrap_or_else(|e| e.exit());
+        run_rocket(Sessions::new(
+            max_sessions,
+            Duration::from_secs(timeout * 60),
+        ));
     }
 }
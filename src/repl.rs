@@ -1,26 +1,89 @@
-use calculator::calc::Calculator;
+use calculator::calc::{Calculator, CalculatorError};
+
+use crate::commands::{self, read_state, write_state, Flow};
+use crate::helper::ReplHelper;
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-pub fn repl() {
-    let mut rl = Editor::<()>::new();
+const PROMPT: &str = ">> ";
+
+/// Returns the path of a file the REPL keeps between runs, in the user's
+/// data directory, or `None` if there is no data directory.
+fn data_file(name: &str) -> Option<PathBuf> {
+    let dir = dirs::data_dir()?.join("rustulator");
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir.join(name))
+}
+
+/// Prints a calculation error, underlining the offending part of the input
+/// beneath the prompt when its location is known.
+fn print_error(line: &str, err: &CalculatorError) {
+    if let Some(span) = err.span() {
+        // Spans past the end of the line point into implied closing parens.
+        let start = span.start.min(line.len());
+        let end = span.end.min(line.len()).max(start);
+        let offset = PROMPT.len() + line[..start].chars().count();
+        let width = line[start..end].chars().count().max(1);
+        println!("{}{}", " ".repeat(offset), "^".repeat(width));
+    }
+    println!("Error: {}", err);
+}
+
+/// Runs the REPL. Unless `restore` is false, it starts with the variables,
+/// functions, history and settings it had when it last quit, and unless
+/// `save` is false, it keeps them for next time.
+pub fn repl(restore: bool, save: bool) {
+    let state = data_file("session.json");
     let mut calculator = Calculator::new();
+    if let Some(state) = state.as_ref().filter(|state| restore && state.exists()) {
+        match read_state(state) {
+            Ok(restored) => calculator = restored,
+            Err(e) => println!("Error: Cannot restore the last session: {}", e),
+        }
+    }
+    // The helper needs the calculator too, to complete names and hint at
+    // the value of each line as it is typed.
+    let calculator = Rc::new(RefCell::new(calculator));
+
+    let mut rl = Editor::<ReplHelper>::new();
+    rl.set_helper(Some(ReplHelper::new(calculator.clone())));
+    let history = data_file("history.txt");
+    if let Some(history) = &history {
+        // There is no history the first time the REPL is run.
+        let _ = rl.load_history(history);
+    }
+
     println!("Welcome to Rustulator!");
-    println!("Currently, arithmetic and abs, floor, log, ln, and trig functions are supported.");
+    println!("Currently, arithmetic, trig, log, rounding, and min/max functions are supported.");
+    println!("Numbers can have units, as in 3 km + 200 m, converted with to: 60 mph to m/s.");
+    println!("Settings and other commands start with #, as in #mode exact or #angle deg;");
+    println!("type #help to list them. Tab completes names and commands.");
     println!("Use Ctrl-C or type #quit to quit.");
     loop {
-        let readline = rl.readline(">> ");
+        let readline = rl.readline(PROMPT);
         match readline {
             Ok(line) => {
-                // a more extensive command system can be added later if desired.
-                if line == String::from("#quit") {
-                    break;
+                if !line.trim().is_empty() {
+                    rl.add_history_entry(line.as_str());
+                }
+                let mut calculator = calculator.borrow_mut();
+                if line.trim_start().starts_with('#') {
+                    if commands::run(&mut calculator, &line) == Flow::Quit {
+                        break;
+                    }
+                    continue;
                 }
 
                 match calculator.calculate(&line) {
-                    Ok(val) => println!("{}", val),
-                    Err(e) => println!("{}", e),
+                    Ok(Some(val)) => println!("{}", calculator.format(&val)),
+                    Ok(None) => {}
+                    Err(e) => print_error(&line, &e),
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -37,4 +100,15 @@ pub fn repl() {
             }
         }
     }
+
+    if let Some(history) = &history {
+        if let Err(e) = rl.save_history(history) {
+            println!("Error: Cannot save the line history: {}", e);
+        }
+    }
+    if let Some(state) = state.filter(|_| save) {
+        if let Err(e) = write_state(&calculator.borrow(), &state) {
+            println!("Error: Cannot save the session: {}", e);
+        }
+    }
 }
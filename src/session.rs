@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use calculator::calc::Calculator;
+use rocket::http::{Cookie, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::{Outcome, State};
+
+/// The name of the private cookie holding a client's session id.
+const COOKIE: &str = "session";
+
+/// A client's calculator, along with when it was last used.
+struct Session {
+    calculator: Arc<Mutex<Calculator>>,
+    last_used: Instant,
+}
+
+struct Live {
+    next_id: u64,
+    sessions: HashMap<u64, Session>,
+}
+
+/// The calculators of every client of the web server, keyed by the session
+/// id in each client's cookie. Sessions left idle for too long are dropped,
+/// and no more than a set number can be live at once.
+pub struct Sessions {
+    live: Mutex<Live>,
+    max_sessions: usize,
+    idle_timeout: Duration,
+}
+
+impl Sessions {
+    pub fn new(max_sessions: usize, idle_timeout: Duration) -> Self {
+        // Ids count up from when the server started, so that a cookie left
+        // over from an earlier run never matches a session of this one.
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64);
+        Sessions {
+            live: Mutex::new(Live {
+                next_id: started,
+                sessions: HashMap::new(),
+            }),
+            max_sessions,
+            idle_timeout,
+        }
+    }
+
+    /// Returns the calculator of session `id` along with its id, starting a
+    /// new session if there is no such session or it has expired. Returns
+    /// `None` if a new session is needed but there are already too many.
+    fn get_or_start(&self, id: Option<u64>) -> Option<(u64, Arc<Mutex<Calculator>>)> {
+        let mut live = self.live.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        let idle_timeout = self.idle_timeout;
+        live.sessions
+            .retain(|_, session| now.duration_since(session.last_used) < idle_timeout);
+
+        if let Some(id) = id {
+            if let Some(session) = live.sessions.get_mut(&id) {
+                session.last_used = now;
+                return Some((id, session.calculator.clone()));
+            }
+        }
+        if live.sessions.len() >= self.max_sessions {
+            return None;
+        }
+
+        let id = live.next_id;
+        live.next_id += 1;
+        let calculator = Arc::new(Mutex::new(Calculator::new()));
+        let session = Session {
+            calculator: calculator.clone(),
+            last_used: now,
+        };
+        live.sessions.insert(id, session);
+        Some((id, calculator))
+    }
+}
+
+/// The calculator of the client making a request. Clients without a live
+/// session are given a new one, unless the server already has as many as
+/// it allows, in which case the request fails with 503 Service Unavailable.
+pub struct SessionCalculator(Arc<Mutex<Calculator>>);
+
+impl SessionCalculator {
+    /// Locks the calculator. A request that panicked while holding the lock
+    /// poisons it, but at worst leaves variables assigned as a failed
+    /// calculation does, so the session carries on rather than failing every
+    /// request after.
+    pub fn lock(&self) -> MutexGuard<Calculator> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for SessionCalculator {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        let sessions = match request.guard::<State<Sessions>>() {
+            Outcome::Success(sessions) => sessions,
+            _ => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+        // The cookie is private, so it is encrypted and clients cannot
+        // forge the id of another client's session.
+        let mut cookies = request.cookies();
+        let id = cookies
+            .get_private(COOKIE)
+            .and_then(|cookie| cookie.value().parse().ok());
+
+        match sessions.get_or_start(id) {
+            Some((session, calculator)) => {
+                if Some(session) != id {
+                    cookies.add_private(Cookie::new(COOKIE, session.to_string()));
+                }
+                Outcome::Success(SessionCalculator(calculator))
+            }
+            None => Outcome::Failure((Status::ServiceUnavailable, ())),
+        }
+    }
+}
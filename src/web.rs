@@ -1,33 +1,370 @@
+use std::collections::BTreeMap;
 use std::io;
-use std::sync::RwLock;
 
+use rocket::http::Status;
+use rocket::response::status::{BadRequest, Custom, NoContent};
 use rocket::response::NamedFile;
-use rocket::{get, post, State};
+use rocket::{delete, get, post, put};
 use rocket_contrib::json::Json;
+use serde::Deserializer;
 use serde_derive::{Deserialize, Serialize};
 
-use calculator::calc::Calculator;
+use calculator::calc::{
+    AngleMode, Calculator, CalculatorError, ComplexForm, ErrorKind, FractionForm, Notation,
+    NumberMode, Value,
+};
+
+use crate::session::SessionCalculator;
 
 #[derive(Serialize, Deserialize)]
 pub struct Calculation {
     calc: String,
 }
 
+/// The outcome of a calculation, which has a value unless there is an error.
+#[derive(Serialize)]
+pub struct CalculationResult {
+    /// The value as a JSON number, or null if it is not a real number.
+    value: Option<f64>,
+    /// The value written out in the current format. Empty for errors and
+    /// function definitions, which have no value.
+    formatted: String,
+    error: Option<CalculationError>,
+    /// The variables after the calculation, written out in the current format.
+    variables: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub struct CalculationError {
+    /// One of `lex`, `parse` or `eval`.
+    kind: String,
+    message: String,
+    /// Where in the input the error is, if that is known.
+    span: Option<ErrorSpan>,
+    /// The offending part of the input, if that is known.
+    token: Option<String>,
+}
+
+/// A byte range into the input.
+#[derive(Serialize)]
+pub struct ErrorSpan {
+    start: usize,
+    end: usize,
+}
+
+/// A value, as a JSON number if it is a real number and written out in the
+/// current format.
+#[derive(Serialize)]
+pub struct Variable {
+    value: Option<f64>,
+    formatted: String,
+}
+
+impl Variable {
+    fn of(calc: &Calculator, val: &Value) -> Self {
+        Variable {
+            value: val.as_real(),
+            formatted: calc.format(val),
+        }
+    }
+}
+
+/// What was undone or redone, and the variables after it.
+#[derive(Serialize)]
+pub struct Change {
+    /// The calculation undone or redone, such as `x = 2`, or `unset x` or
+    /// `clear` for a variable removed or a session cleared.
+    change: String,
+    /// The variables, written out in the current format.
+    variables: BTreeMap<String, String>,
+}
+
+impl Change {
+    fn of(calc: &Calculator, change: String) -> Self {
+        let variables = calc
+            .variables()
+            .iter()
+            .map(|(name, val)| (name.clone(), calc.format(val)))
+            .collect();
+        Change { change, variables }
+    }
+}
+
+/// A calculation from the history, with the value it had at the time.
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    input: String,
+    value: Option<f64>,
+    formatted: String,
+}
+
+/// Parse errors are the client's fault, while evaluation errors are in
+/// input that is well formed but cannot be calculated.
+fn error_status(err: &CalculatorError) -> Status {
+    match err.kind() {
+        ErrorKind::Lex | ErrorKind::Parse => Status::BadRequest,
+        ErrorKind::Eval => Status::UnprocessableEntity,
+    }
+}
+
+impl CalculationError {
+    fn of(err: &CalculatorError) -> Self {
+        CalculationError {
+            kind: err.kind().to_string(),
+            message: err.message().to_string(),
+            span: err.span().map(|span| ErrorSpan {
+                start: span.start,
+                end: span.end,
+            }),
+            token: err.token().map(|token| token.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Settings {
+    angle: String,
+    mode: String,
+    form: String,
+    fraction: String,
+    precision: u32,
+    notation: String,
+    /// Null when every digit is written out.
+    digits: Option<u32>,
+    separators: bool,
+}
+
+/// Changes to the settings, leaving out any that stay the same.
+#[derive(Deserialize)]
+pub struct SettingsUpdate {
+    angle: Option<String>,
+    mode: Option<String>,
+    form: Option<String>,
+    fraction: Option<String>,
+    precision: Option<u32>,
+    notation: Option<String>,
+    /// A null number of digits goes back to writing every digit, so it is
+    /// told apart from one that is left out.
+    #[serde(default, deserialize_with = "present")]
+    digits: Option<Option<u32>>,
+    separators: Option<bool>,
+}
+
+/// Reads a setting that may be null, which is there if it is read at all.
+fn present<'de, D>(deserializer: D) -> Result<Option<Option<u32>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    serde::Deserialize::deserialize(deserializer).map(Some)
+}
+
+impl Settings {
+    fn of(calc: &Calculator) -> Self {
+        Settings {
+            angle: calc.angle_mode().to_string(),
+            mode: calc.number_mode().to_string(),
+            form: calc.complex_form().to_string(),
+            fraction: calc.fraction_form().to_string(),
+            precision: calc.precision(),
+            notation: calc.notation().to_string(),
+            digits: calc.digits(),
+            separators: calc.separators(),
+        }
+    }
+}
+
 #[get("/")]
 pub fn get_index() -> io::Result<NamedFile> {
     NamedFile::open("static/index.html")
 }
 
+/// Calculates the input. Invalid input is answered with 400 Bad Request if
+/// it cannot be parsed, and 422 Unprocessable Entity if it cannot be evaluated.
 #[post("/", format = "application/json", data = "<calculation>")]
 pub fn calculate(
-    calculator: State<RwLock<Calculator>>,
+    session: SessionCalculator,
     calculation: Json<Calculation>,
-) -> Json<String> {
+) -> Custom<Json<CalculationResult>> {
     let input = &calculation.0.calc;
-    let mut calc = calculator.write().unwrap();
-    let output = match calc.calculate(input) {
-        Ok(n) => format!("{}", n),
-        Err(e) => format!("{}", e),
+    let mut calc = session.lock();
+    let (status, value, error) = match calc.calculate(input) {
+        Ok(value) => (Status::Ok, value, None),
+        Err(e) => (error_status(&e), None, Some(CalculationError::of(&e))),
+    };
+    let variables = calc
+        .variables()
+        .iter()
+        .map(|(name, val)| (name.clone(), calc.format(val)))
+        .collect();
+    let result = CalculationResult {
+        value: value.as_ref().and_then(|val| val.as_real()),
+        formatted: value.as_ref().map_or(String::new(), |val| calc.format(val)),
+        error,
+        variables,
     };
-    Json(output)
+    Custom(status, Json(result))
+}
+
+#[get("/history")]
+pub fn get_history(session: SessionCalculator) -> Json<Vec<HistoryEntry>> {
+    let calc = session.lock();
+    let history = calc
+        .get_log()
+        .into_iter()
+        .map(|(input, val)| HistoryEntry {
+            value: val.as_real(),
+            formatted: calc.format(&val),
+            input,
+        })
+        .collect();
+    Json(history)
+}
+
+#[get("/variables")]
+pub fn get_variables(session: SessionCalculator) -> Json<BTreeMap<String, Variable>> {
+    let calc = session.lock();
+    let variables = calc
+        .variables()
+        .iter()
+        .map(|(name, val)| (name.clone(), Variable::of(&calc, val)))
+        .collect();
+    Json(variables)
+}
+
+/// Answers with 404 Not Found if there is no such variable.
+#[get("/variables/<name>")]
+pub fn get_variable(session: SessionCalculator, name: String) -> Option<Json<Variable>> {
+    let calc = session.lock();
+    calc.get_variable(&name)
+        .map(|val| Json(Variable::of(&calc, &val)))
+}
+
+/// Sets a variable to the value of the calculation, without adding it to
+/// the history. Errors have the same statuses as they do for `calculate`.
+#[put(
+    "/variables/<name>",
+    format = "application/json",
+    data = "<calculation>"
+)]
+pub fn put_variable(
+    session: SessionCalculator,
+    name: String,
+    calculation: Json<Calculation>,
+) -> Result<Json<Variable>, Custom<Json<CalculationError>>> {
+    let mut calc = session.lock();
+    match calc.set_variable(&name, &calculation.0.calc) {
+        Ok(val) => Ok(Json(Variable::of(&calc, &val))),
+        Err(e) => Err(Custom(error_status(&e), Json(CalculationError::of(&e)))),
+    }
+}
+
+/// Answers with the value the variable had, or 404 Not Found if there is
+/// no such variable.
+#[delete("/variables/<name>")]
+pub fn delete_variable(session: SessionCalculator, name: String) -> Option<Json<Variable>> {
+    let mut calc = session.lock();
+    calc.remove_variable(&name)
+        .map(|val| Json(Variable::of(&calc, &val)))
+}
+
+/// Clears the history, variables and functions of the session, but keeps
+/// its settings.
+#[delete("/session")]
+pub fn delete_session(session: SessionCalculator) -> NoContent {
+    session.lock().clear();
+    NoContent
+}
+
+/// Undoes the last calculation or change to the variables, answering with
+/// 404 Not Found if there is nothing to undo.
+#[post("/undo")]
+pub fn undo(session: SessionCalculator) -> Option<Json<Change>> {
+    let mut calc = session.lock();
+    let change = calc.undo()?;
+    Some(Json(Change::of(&calc, change)))
+}
+
+/// Redoes what was last undone, answering with 404 Not Found if there is
+/// nothing to redo.
+#[post("/redo")]
+pub fn redo(session: SessionCalculator) -> Option<Json<Change>> {
+    let mut calc = session.lock();
+    let change = calc.redo()?;
+    Some(Json(Change::of(&calc, change)))
+}
+
+#[get("/settings")]
+pub fn get_settings(session: SessionCalculator) -> Json<Settings> {
+    let calc = session.lock();
+    Json(Settings::of(&calc))
+}
+
+#[put("/settings", format = "application/json", data = "<settings>")]
+pub fn put_settings(
+    session: SessionCalculator,
+    settings: Json<SettingsUpdate>,
+) -> Result<Json<Settings>, BadRequest<String>> {
+    let settings = settings.0;
+    // Check every setting before changing any of them.
+    let angle = settings
+        .angle
+        .map(|angle| angle.parse::<AngleMode>())
+        .transpose()
+        .map_err(|e| BadRequest(Some(e)))?;
+    let mode = settings
+        .mode
+        .map(|mode| mode.parse::<NumberMode>())
+        .transpose()
+        .map_err(|e| BadRequest(Some(e)))?;
+    let form = settings
+        .form
+        .map(|form| form.parse::<ComplexForm>())
+        .transpose()
+        .map_err(|e| BadRequest(Some(e)))?;
+    let fraction = settings
+        .fraction
+        .map(|fraction| fraction.parse::<FractionForm>())
+        .transpose()
+        .map_err(|e| BadRequest(Some(e)))?;
+    let notation = settings
+        .notation
+        .map(|notation| notation.parse::<Notation>())
+        .transpose()
+        .map_err(|e| BadRequest(Some(e)))?;
+
+    let mut calc = session.lock();
+    // The precision and digits are checked by setting them, so the precision
+    // is put back if the digits turn out to be out of range.
+    let precision = calc.precision();
+    let checked = settings
+        .precision
+        .map_or(Ok(()), |precision| calc.set_precision(precision))
+        .and_then(|_| {
+            settings
+                .digits
+                .map_or(Ok(()), |digits| calc.set_digits(digits))
+        });
+    if let Err(e) = checked {
+        calc.set_precision(precision).ok();
+        return Err(BadRequest(Some(e)));
+    }
+    if let Some(angle) = angle {
+        calc.set_angle_mode(angle);
+    }
+    if let Some(mode) = mode {
+        calc.set_number_mode(mode);
+    }
+    if let Some(form) = form {
+        calc.set_complex_form(form);
+    }
+    if let Some(fraction) = fraction {
+        calc.set_fraction_form(fraction);
+    }
+    if let Some(notation) = notation {
+        calc.set_notation(notation);
+    }
+    if let Some(separators) = settings.separators {
+        calc.set_separators(separators);
+    }
+    Ok(Json(Settings::of(&calc)))
 }